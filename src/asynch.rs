@@ -0,0 +1,84 @@
+//! Async driver, built on `embedded-hal-async`.
+
+use embedded_hal_1::digital::{InputPin, OutputPin};
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::digital::Wait;
+use embedded_hal_async::i2c::I2c;
+use embedded_hal_async::spi::{Operation, SpiDevice};
+
+use crate::interface::{I2cInterface, SpiInterface, SPI_CMD_READ, SPI_CMD_WRITE, SPI_DUMMY};
+use crate::{Error, TouchReport};
+
+/// Async register level access to the controller, see `crate::interface::Interface`.
+#[allow(async_fn_in_trait)]
+pub trait Interface {
+    type Error;
+
+    /// Read consecutive registers starting at `reg`.
+    async fn read_regs(&mut self, reg: u8, buf: &mut [u8]) -> Result<(), Self::Error>;
+
+    async fn write_reg8(&mut self, reg: u8, value: u8) -> Result<(), Self::Error>;
+}
+
+impl<I2C> Interface for I2cInterface<I2C>
+where
+    I2C: I2c,
+{
+    type Error = I2C::Error;
+
+    async fn read_regs(&mut self, reg: u8, buf: &mut [u8]) -> Result<(), Self::Error> {
+        self.i2c.write_read(self.addr, &[reg], buf).await
+    }
+
+    async fn write_reg8(&mut self, reg: u8, value: u8) -> Result<(), Self::Error> {
+        self.i2c.write(self.addr, &[reg, value]).await
+    }
+}
+
+impl<SPI> Interface for SpiInterface<SPI>
+where
+    SPI: SpiDevice,
+{
+    type Error = SPI::Error;
+
+    async fn read_regs(&mut self, reg: u8, buf: &mut [u8]) -> Result<(), Self::Error> {
+        self.spi
+            .transaction(&mut [
+                Operation::Write(&[SPI_CMD_READ, reg, SPI_DUMMY]),
+                Operation::Read(buf),
+            ])
+            .await
+    }
+
+    async fn write_reg8(&mut self, reg: u8, value: u8) -> Result<(), Self::Error> {
+        self.spi.write(&[SPI_CMD_WRITE, reg, value]).await
+    }
+}
+
+crate::driver::driver!([async] [.await]);
+
+impl<IFACE, INT, RST> TouchIC<IFACE, INT, RST>
+where
+    IFACE: Interface,
+    INT: InputPin,
+{
+    /// Check `INTn` without touching the bus.
+    pub fn touch_pending(&mut self) -> Result<bool, Error<IFACE::Error>> {
+        self.int.is_low().map_err(|_| Error::Pin)
+    }
+}
+
+impl<IFACE, INT, RST> TouchIC<IFACE, INT, RST>
+where
+    IFACE: Interface,
+    INT: Wait,
+{
+    /// Wait until `INTn` is asserted, then read the touch report.
+    ///
+    /// The bus is only accessed once the controller has asserted a report. The report may be
+    /// empty, e.g. the one for a finger lift, so it can be fed to `tracker::Tracker` as is.
+    pub async fn wait_for_touch(&mut self) -> Result<TouchReport, Error<IFACE::Error>> {
+        self.int.wait_for_low().await.map_err(|_| Error::Pin)?;
+        self.read_report().await
+    }
+}
@@ -0,0 +1,247 @@
+//! Affine touch calibration.
+//!
+//! Corrects per-unit mechanical tolerances with
+//! `x' = a * x + b * y + c` and `y' = d * x + e * y + f`,
+//! solved from pairs of reference (where the target was drawn) and measured (what the
+//! driver reported) points.
+
+use crate::Point;
+
+const FRAC_BITS: u32 = 16;
+const ONE: i32 = 1 << FRAC_BITS;
+const BLOB_VERSION: u8 = 1;
+
+/// Affine calibration matrix, coefficients `[a, b, c, d, e, f]` in Q16.16 fixed-point.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Calibration {
+    coeffs: [i32; 6],
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Calibration {
+    pub const IDENTITY: Self = Self {
+        coeffs: [ONE, 0, 0, 0, ONE, 0],
+    };
+
+    /// Size of the serialized form: version, coefficients, checksum.
+    pub const BLOB_LEN: usize = 1 + 6 * 4 + 1;
+
+    /// Build from raw Q16.16 coefficients `[a, b, c, d, e, f]`.
+    pub const fn from_coefficients(coeffs: [i32; 6]) -> Self {
+        Self { coeffs }
+    }
+
+    pub const fn coefficients(&self) -> [i32; 6] {
+        self.coeffs
+    }
+
+    /// Exact solution from 3 non-collinear point pairs.
+    pub fn from_3_points(reference: &[Point; 3], measured: &[Point; 3]) -> Option<Self> {
+        Self::from_points(reference, measured)
+    }
+
+    /// Least-squares solution from 3 or more point pairs.
+    ///
+    /// Returns `None` if the slices differ in length, fewer than 3 pairs are given, the
+    /// measured points are collinear, or a coefficient does not fit Q16.16.
+    pub fn from_points(reference: &[Point], measured: &[Point]) -> Option<Self> {
+        if reference.len() != measured.len() || measured.len() < 3 {
+            return None;
+        }
+        let n = measured.len() as f64;
+
+        // Center the points to keep the normal equations well conditioned.
+        let mean = |points: &[Point]| {
+            let (sx, sy) = points.iter().fold((0.0, 0.0), |(sx, sy), p| {
+                (sx + f64::from(p.x), sy + f64::from(p.y))
+            });
+            (sx / n, sy / n)
+        };
+        let (mx, my) = mean(measured);
+        let (rx, ry) = mean(reference);
+
+        let (mut suu, mut suv, mut svv) = (0.0, 0.0, 0.0);
+        let (mut sux, mut svx, mut suy, mut svy) = (0.0, 0.0, 0.0, 0.0);
+        for (r, m) in reference.iter().zip(measured) {
+            let (u, v) = (f64::from(m.x) - mx, f64::from(m.y) - my);
+            let (x, y) = (f64::from(r.x) - rx, f64::from(r.y) - ry);
+            suu += u * u;
+            suv += u * v;
+            svv += v * v;
+            sux += u * x;
+            svx += v * x;
+            suy += u * y;
+            svy += v * y;
+        }
+
+        let det = suu * svv - suv * suv;
+        // Collinear or coincident points, relative to the spread of the samples.
+        if det <= 1e-9 * suu * svv {
+            return None;
+        }
+        let a = (sux * svv - svx * suv) / det;
+        let b = (svx * suu - sux * suv) / det;
+        let d = (suy * svv - svy * suv) / det;
+        let e = (svy * suu - suy * suv) / det;
+        let c = rx - a * mx - b * my;
+        let f = ry - d * mx - e * my;
+
+        let mut coeffs = [0; 6];
+        for (q, v) in coeffs.iter_mut().zip([a, b, c, d, e, f]) {
+            *q = to_fixed(v)?;
+        }
+        Some(Self { coeffs })
+    }
+
+    /// Apply to a point, rounding to nearest and clamping to the `u16` range.
+    pub fn apply(&self, p: Point) -> Point {
+        let [a, b, c, d, e, f] = self.coeffs.map(i64::from);
+        let (x, y) = (i64::from(p.x), i64::from(p.y));
+        Point {
+            x: from_fixed(a * x + b * y + c),
+            y: from_fixed(d * x + e * y + f),
+        }
+    }
+
+    /// Serialize for storage, e.g. in flash.
+    pub fn to_bytes(&self) -> [u8; Self::BLOB_LEN] {
+        let mut blob = [0u8; Self::BLOB_LEN];
+        blob[0] = BLOB_VERSION;
+        for (chunk, coeff) in blob[1..].chunks_exact_mut(4).zip(self.coeffs) {
+            chunk.copy_from_slice(&coeff.to_le_bytes());
+        }
+        blob[Self::BLOB_LEN - 1] = checksum(&blob[..Self::BLOB_LEN - 1]);
+        blob
+    }
+
+    /// Deserialize a blob from `to_bytes`.
+    ///
+    /// Returns `None` on version or checksum mismatch, e.g. for erased flash.
+    pub fn from_bytes(blob: &[u8; Self::BLOB_LEN]) -> Option<Self> {
+        let (body, sum) = blob.split_at(Self::BLOB_LEN - 1);
+        if body[0] != BLOB_VERSION || checksum(body) != sum[0] {
+            return None;
+        }
+        let mut coeffs = [0; 6];
+        for (coeff, chunk) in coeffs.iter_mut().zip(body[1..].chunks_exact(4)) {
+            *coeff = i32::from_le_bytes(chunk.try_into().unwrap());
+        }
+        Some(Self { coeffs })
+    }
+}
+
+fn to_fixed(v: f64) -> Option<i32> {
+    let q = v * f64::from(ONE);
+    let q = if q >= 0.0 { q + 0.5 } else { q - 0.5 };
+    if q >= f64::from(i32::MIN) && q <= f64::from(i32::MAX) {
+        Some(q as i32)
+    } else {
+        None
+    }
+}
+
+fn from_fixed(q: i64) -> u16 {
+    let v = (q + i64::from(ONE / 2)) >> FRAC_BITS;
+    v.clamp(0, i64::from(u16::MAX)) as u16
+}
+
+/// Wrapping sum, inverted so an all-zero blob does not verify.
+fn checksum(bytes: &[u8]) -> u8 {
+    !bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn p(x: u16, y: u16) -> Point {
+        Point { x, y }
+    }
+
+    fn assert_close(a: Point, b: Point, tolerance: u16) {
+        assert!(
+            a.x.abs_diff(b.x) <= tolerance && a.y.abs_diff(b.y) <= tolerance,
+            "{a:?} != {b:?}"
+        );
+    }
+
+    #[test]
+    fn three_points_map_measured_onto_reference() {
+        let reference = [p(100, 100), p(900, 120), p(480, 700)];
+        let measured = [p(130, 90), p(880, 140), p(500, 650)];
+        let calibration = Calibration::from_3_points(&reference, &measured).unwrap();
+        for (r, m) in reference.iter().zip(&measured) {
+            assert_close(calibration.apply(*m), *r, 1);
+        }
+    }
+
+    #[test]
+    fn least_squares_with_noise() {
+        // measured = reference scaled by 0.9 / 1.1, shifted and skewed, plus +-2 of noise
+        let noise = [2i32, -1, 0, -2, 1, 2, -2, 0, 1];
+        let mut reference = [p(0, 0); 9];
+        let mut measured = [p(0, 0); 9];
+        for (i, (r, m)) in reference.iter_mut().zip(&mut measured).enumerate() {
+            let (x, y) = (100 + 300 * (i % 3) as i32, 100 + 250 * (i / 3) as i32);
+            *r = p(x as u16, y as u16);
+            let mx = x * 9 / 10 + y / 20 + 40 + noise[i];
+            let my = y * 11 / 10 - 15 - noise[8 - i];
+            *m = p(mx as u16, my as u16);
+        }
+        let calibration = Calibration::from_points(&reference, &measured).unwrap();
+        for (r, m) in reference.iter().zip(&measured) {
+            assert_close(calibration.apply(*m), *r, 4);
+        }
+    }
+
+    #[test]
+    fn collinear_points_are_rejected() {
+        let reference = [p(0, 0), p(100, 100), p(200, 200)];
+        let measured = [p(10, 10), p(110, 110), p(210, 210)];
+        assert_eq!(Calibration::from_3_points(&reference, &measured), None);
+    }
+
+    #[test]
+    fn mismatched_or_too_few_points_are_rejected() {
+        let points = [p(0, 0), p(100, 0), p(0, 100)];
+        assert_eq!(Calibration::from_points(&points, &points[..2]), None);
+        assert_eq!(Calibration::from_points(&points[..2], &points[..2]), None);
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let calibration =
+            Calibration::from_coefficients([65_000, -120, 1 << 20, 300, 66_000, -5 << 16]);
+        let blob = calibration.to_bytes();
+        assert_eq!(Calibration::from_bytes(&blob), Some(calibration));
+    }
+
+    #[test]
+    fn erased_and_corrupted_blobs_are_rejected() {
+        assert_eq!(
+            Calibration::from_bytes(&[0xFF; Calibration::BLOB_LEN]),
+            None
+        );
+        assert_eq!(
+            Calibration::from_bytes(&[0x00; Calibration::BLOB_LEN]),
+            None
+        );
+
+        let mut blob = Calibration::IDENTITY.to_bytes();
+        blob[5] ^= 0x04;
+        assert_eq!(Calibration::from_bytes(&blob), None);
+    }
+
+    #[test]
+    fn identity_and_clamping() {
+        assert_eq!(Calibration::IDENTITY.apply(p(123, 456)), p(123, 456));
+        let shift = Calibration::from_coefficients([ONE, 0, -1000 * ONE, 0, ONE, 1000 * ONE]);
+        assert_eq!(shift.apply(p(10, u16::MAX - 10)), p(0, u16::MAX));
+    }
+}
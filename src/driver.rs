@@ -0,0 +1,517 @@
+//! Driver logic shared by the blocking and async `TouchIC`.
+
+/// Defines `TouchIC` and all of its bus independent methods.
+///
+/// Expanded as `driver!([] [])` in the crate root and as `driver!([async] [.await])` in
+/// `asynch`. The bus, delay and pin traits (`Interface`, `I2c`, `SpiDevice`, `DelayNs`,
+/// `OutputPin`) resolve at the expansion site, so each flavor picks up its own, while
+/// everything else is written once and cannot drift.
+macro_rules! driver {
+    ([$($async:tt)*] [$($await:tt)*]) => {
+        pub struct TouchIC<IFACE, INT = $crate::NoPin, RST = $crate::NoPin> {
+            iface: IFACE,
+            int: INT,
+            rst: Option<RST>,
+            cfg: $crate::Config,
+            caps: Option<$crate::Capabilities>,
+            last_counter: Option<u16>,
+        }
+
+        impl<I2C> TouchIC<$crate::interface::I2cInterface<I2C>>
+        where
+            I2C: I2c,
+        {
+            pub fn new(i2c: I2C, addr: u8) -> Self {
+                Self::new_with_interface($crate::interface::I2cInterface::new(i2c, addr))
+            }
+
+            pub fn new_default(i2c: I2C) -> Self {
+                Self::new(i2c, $crate::DEFAULT_ADDR)
+            }
+
+            /// Try each candidate address in turn and return a driver for the first one whose
+            /// status and capability registers look like a Sitronix controller.
+            pub $($async)* fn probe_addresses(
+                i2c: I2C,
+                addrs: &[u8],
+            ) -> Result<Self, $crate::NotFound<I2C>> {
+                let mut this = Self::new(i2c, $crate::DEFAULT_ADDR);
+                for &addr in addrs {
+                    this.iface.addr = addr;
+                    if this.is_sitronix()$($await)* {
+                        return Ok(this);
+                    }
+                }
+                Err($crate::NotFound {
+                    i2c: this.release(),
+                })
+            }
+
+            $($async)* fn is_sitronix(&mut self) -> bool {
+                let Ok(status) = self.get_status()$($await)* else {
+                    return false;
+                };
+                let Ok(caps) = self.get_capabilities()$($await)* else {
+                    return false;
+                };
+                $crate::is_plausible_device(status, &caps)
+            }
+        }
+
+        impl<I2C, INT, RST> TouchIC<$crate::interface::I2cInterface<I2C>, INT, RST> {
+            /// Recover the bus. Attached pins are dropped.
+            pub fn release(self) -> I2C {
+                self.iface.i2c
+            }
+        }
+
+        impl<SPI> TouchIC<$crate::interface::SpiInterface<SPI>>
+        where
+            SPI: SpiDevice,
+        {
+            pub fn new_spi(spi: SPI) -> Self {
+                Self::new_with_interface($crate::interface::SpiInterface::new(spi))
+            }
+        }
+
+        impl<SPI, INT, RST> TouchIC<$crate::interface::SpiInterface<SPI>, INT, RST> {
+            /// Recover the SPI device. Attached pins are dropped.
+            pub fn release(self) -> SPI {
+                self.iface.spi
+            }
+        }
+
+        impl<IFACE> TouchIC<IFACE>
+        where
+            IFACE: Interface,
+        {
+            pub fn new_with_interface(iface: IFACE) -> Self {
+                Self {
+                    iface,
+                    int: $crate::NoPin,
+                    rst: None,
+                    cfg: $crate::Config::default(),
+                    caps: None,
+                    last_counter: None,
+                }
+            }
+        }
+
+        impl<IFACE, INT, RST> TouchIC<IFACE, INT, RST>
+        where
+            IFACE: Interface,
+        {
+            /// Attach the `INTn` pin, which the controller pulls low while a touch report is pending.
+            pub fn with_int_pin<P>(self, int: P) -> TouchIC<IFACE, P, RST> {
+                TouchIC {
+                    iface: self.iface,
+                    int,
+                    rst: self.rst,
+                    cfg: self.cfg,
+                    caps: self.caps,
+                    last_counter: self.last_counter,
+                }
+            }
+
+            /// Attach the active-low `RSTn` pin, used by `hard_reset` and `init`.
+            pub fn with_reset_pin<P>(self, rst: P) -> TouchIC<IFACE, INT, P> {
+                TouchIC {
+                    iface: self.iface,
+                    int: self.int,
+                    rst: Some(rst),
+                    cfg: self.cfg,
+                    caps: self.caps,
+                    last_counter: self.last_counter,
+                }
+            }
+
+            /// Time budget for `init` to see the controller ready, in milliseconds.
+            pub fn with_init_timeout(mut self, timeout_ms: u32) -> Self {
+                self.cfg.init_timeout_ms = timeout_ms;
+                self
+            }
+
+            /// Select the chip model, which determines the touch report layout.
+            pub fn with_model(mut self, model: $crate::ChipModel) -> Self {
+                self.cfg.model = model;
+                self
+            }
+
+            pub fn model(&self) -> $crate::ChipModel {
+                self.cfg.model
+            }
+
+            /// Map reported points from sensor to display coordinates, using the sensor
+            /// resolution from `get_capabilities`.
+            pub fn with_transform(mut self, transform: $crate::Transform) -> Self {
+                self.cfg.transform = transform;
+                self
+            }
+
+            pub fn set_transform(&mut self, transform: $crate::Transform) {
+                self.cfg.transform = transform;
+            }
+
+            /// Rescale reported points from the sensor resolution to a `width` x `height` display,
+            /// after the orientation transform.
+            pub fn with_display_size(mut self, width: u16, height: u16) -> Self {
+                self.cfg.display_size = Some((width, height));
+                self
+            }
+
+            pub fn set_display_size(&mut self, size: Option<(u16, u16)>) {
+                self.cfg.display_size = size;
+            }
+
+            /// Correct reported points with an affine calibration, applied last.
+            pub fn with_calibration(
+                mut self,
+                calibration: $crate::calibration::Calibration,
+            ) -> Self {
+                self.cfg.calibration = Some(calibration);
+                self
+            }
+
+            pub fn set_calibration(
+                &mut self,
+                calibration: Option<$crate::calibration::Calibration>,
+            ) {
+                self.cfg.calibration = calibration;
+            }
+
+            /// Identify the controller from its `CHIP_ID` register and select the matching model.
+            ///
+            /// Fails with `Error::UnsupportedChip` if the device is not a recognized Sitronix controller.
+            /// ST1232 does not implement `CHIP_ID` and has to be selected with `with_model`.
+            pub $($async)* fn probe(
+                &mut self,
+            ) -> Result<$crate::ChipModel, $crate::Error<IFACE::Error>> {
+                let chip_id = self.read_reg8($crate::regs::CHIP_ID)$($await)*?;
+                let model =
+                    $crate::ChipModel::from_chip_id(chip_id).ok_or($crate::Error::UnsupportedChip)?;
+                self.cfg.model = model;
+                Ok(model)
+            }
+
+            /// Gesture codes not defined by the protocol are reported as `GestureType::Unknown`.
+            pub $($async)* fn get_gesture_info(
+                &mut self,
+            ) -> Result<$crate::GestureInfo, $crate::Error<IFACE::Error>> {
+                if !self.cfg.model.has_gestures() {
+                    return Err($crate::Error::Unsupported);
+                }
+                let raw = self.read_reg8($crate::regs::ADVANCED_TOUCH_INFO)$($await)*?;
+                Ok($crate::GestureInfo::from_raw(raw))
+            }
+
+            pub $($async)* fn get_point0(
+                &mut self,
+            ) -> Result<Option<$crate::Point>, $crate::Error<IFACE::Error>> {
+                self.get_point(0)$($await)*
+            }
+
+            pub $($async)* fn get_point1(
+                &mut self,
+            ) -> Result<Option<$crate::Point>, $crate::Error<IFACE::Error>> {
+                self.get_point(1)$($await)*
+            }
+
+            /// Point in the nth contact slot, a view over the `read_report` burst.
+            ///
+            /// Does not affect `TouchReport::is_new` of the next report.
+            pub $($async)* fn get_point(
+                &mut self,
+                nth: u8,
+            ) -> Result<Option<$crate::Point>, $crate::Error<IFACE::Error>> {
+                Ok(self.read_frame()$($await)*?.point(nth))
+            }
+
+            /// Like `read_report`, but `None` if the controller has not completed a scan since the
+            /// previous report, giving exactly one report per scan.
+            pub $($async)* fn read_new_report(
+                &mut self,
+            ) -> Result<Option<$crate::TouchReport>, $crate::Error<IFACE::Error>> {
+                let report = self.read_report()$($await)*?;
+                Ok(report.is_new().then_some(report))
+            }
+
+            /// Contact in the nth slot, with slot id and strength, a view over the `read_report`
+            /// burst.
+            ///
+            /// Does not affect `TouchReport::is_new` of the next report.
+            pub $($async)* fn get_contact(
+                &mut self,
+                nth: u8,
+            ) -> Result<Option<$crate::Contact>, $crate::Error<IFACE::Error>> {
+                Ok(self.read_frame()$($await)*?.contact(nth))
+            }
+
+            /// Read gesture info and all contact slots in a single burst.
+            ///
+            /// The sensing counter is read in the same burst, see `TouchReport::is_new`.
+            ///
+            /// Once capabilities are cached (by `init`, or by a transform or display size
+            /// needing them), fails with `Error::InvalidCoordinates` if a contact lies outside
+            /// the sensor resolution.
+            pub $($async)* fn read_report(
+                &mut self,
+            ) -> Result<$crate::TouchReport, $crate::Error<IFACE::Error>> {
+                let mut report = self.read_frame()$($await)*?;
+                report.check_new(&mut self.last_counter);
+                Ok(report)
+            }
+
+            /// Sensing Counter Registers provide a frame-based scan counter for host to verify current scan rate.
+            ///
+            /// See `scan_rate::ScanRateMeter` to turn it into frames per second.
+            pub $($async)* fn get_sensor_count(
+                &mut self,
+            ) -> Result<u16, $crate::Error<IFACE::Error>> {
+                let mut buf = [0u8; 2];
+                self.read_regs($crate::regs::SENSING_COUNTER_L, &mut buf)$($await)*?;
+
+                Ok($crate::sensor_count_from_raw(buf))
+            }
+
+            pub $($async)* fn get_capabilities(
+                &mut self,
+            ) -> Result<$crate::Capabilities, $crate::Error<IFACE::Error>> {
+                let max_contacts = self.read_reg8($crate::regs::CONTACT_COUNT_MAX)$($await)*?;
+                let misc_info = self.read_reg8($crate::regs::MISC_INFO)$($await)*?;
+
+                let mut buf = [0u8; 3];
+                self.read_regs($crate::regs::XY_RESOLUTION_H, &mut buf)$($await)*?;
+
+                Ok($crate::Capabilities::from_raw(max_contacts, misc_info, buf))
+            }
+
+            /// Current device state and error code, e.g. to log why the panel left normal mode.
+            pub $($async)* fn get_status(
+                &mut self,
+            ) -> Result<$crate::Status, $crate::Error<IFACE::Error>> {
+                let raw = self.read_reg8($crate::regs::STATUS)$($await)*?;
+                Ok($crate::Status::from_raw(raw))
+            }
+
+            pub $($async)* fn get_device_control(
+                &mut self,
+            ) -> Result<$crate::DeviceControl, $crate::Error<IFACE::Error>> {
+                let raw = self.read_reg8($crate::regs::DEVICE_CONTROL)$($await)*?;
+                Ok($crate::DeviceControl::from_raw(raw))
+            }
+
+            pub $($async)* fn set_device_control(
+                &mut self,
+                ctrl: $crate::DeviceControl,
+            ) -> Result<(), $crate::Error<IFACE::Error>> {
+                self.write_reg8($crate::regs::DEVICE_CONTROL, ctrl.to_raw())$($await)*
+            }
+
+            /// Read-modify-write the Device Control Register.
+            pub $($async)* fn modify_device_control(
+                &mut self,
+                f: impl FnOnce(&mut $crate::DeviceControl),
+            ) -> Result<(), $crate::Error<IFACE::Error>> {
+                let mut ctrl = self.get_device_control()$($await)*?;
+                f(&mut ctrl);
+                self.set_device_control(ctrl)$($await)*
+            }
+
+            /// Issue a software reset, then wait for the controller to be ready as in `init`.
+            pub $($async)* fn soft_reset(
+                &mut self,
+                delay: &mut impl DelayNs,
+            ) -> Result<(), $crate::Error<IFACE::Error>> {
+                self.modify_device_control(|ctrl| ctrl.reset = true)$($await)*?;
+                delay.delay_ms($crate::BOOT_TIME_MS)$($await)*;
+                self.wait_ready(delay)$($await)*
+            }
+
+            /// Stop scanning, e.g. while the display is blanked.
+            pub $($async)* fn power_down(&mut self) -> Result<(), $crate::Error<IFACE::Error>> {
+                self.modify_device_control(|ctrl| ctrl.power_down = true)$($await)*
+            }
+
+            /// Resume scanning after `power_down`.
+            pub $($async)* fn wake_up(&mut self) -> Result<(), $crate::Error<IFACE::Error>> {
+                self.modify_device_control(|ctrl| ctrl.power_down = false)$($await)*
+            }
+
+            pub $($async)* fn set_proximity_sensing(
+                &mut self,
+                enable: bool,
+            ) -> Result<(), $crate::Error<IFACE::Error>> {
+                self.modify_device_control(|ctrl| ctrl.proximity_sensing = enable)$($await)*
+            }
+
+            pub $($async)* fn get_idle_timeout(
+                &mut self,
+            ) -> Result<$crate::IdleTimeout, $crate::Error<IFACE::Error>> {
+                let raw = self.read_reg8($crate::regs::TIMEOUT_TO_IDLE)$($await)*?;
+                Ok($crate::IdleTimeout::from_secs(raw))
+            }
+
+            pub $($async)* fn set_idle_timeout(
+                &mut self,
+                timeout: $crate::IdleTimeout,
+            ) -> Result<(), $crate::Error<IFACE::Error>> {
+                self.write_reg8($crate::regs::TIMEOUT_TO_IDLE, timeout.as_secs())$($await)*
+            }
+
+            /// Firmware version, revision and chip ID, for correlating issues with controller firmware.
+            pub $($async)* fn get_device_info(
+                &mut self,
+            ) -> Result<$crate::DeviceInfo, $crate::Error<IFACE::Error>> {
+                if !self.cfg.model.has_device_info() {
+                    return Err($crate::Error::Unsupported);
+                }
+                let firmware_version = self.read_reg8($crate::regs::FIRMWARE_VERSION)$($await)*?;
+                let chip_id = self.read_reg8($crate::regs::CHIP_ID)$($await)*?;
+
+                let mut buf = [0u8; 4];
+                self.read_regs($crate::regs::FIRMWARE_REVISION, &mut buf)$($await)*?;
+
+                Ok($crate::DeviceInfo {
+                    firmware_version,
+                    firmware_revision: u32::from_be_bytes(buf),
+                    chip_id,
+                })
+            }
+
+            /// Burst read, validate and map a touch report, leaving its sensing counter unchecked.
+            $($async)* fn read_frame(
+                &mut self,
+            ) -> Result<$crate::TouchReport, $crate::Error<IFACE::Error>> {
+                let mut buf = [0u8; $crate::REPORT_LEN];
+                let buf = &mut buf[..self.cfg.model.report_len()];
+                self.read_regs(self.cfg.model.report_start(), buf)$($await)*?;
+
+                let mut report = self.cfg.model.parse_report(buf);
+                let caps = self.mapping_capabilities()$($await)*?;
+                if let Some(caps) = &caps {
+                    if !report.points().all(|p| caps.contains(p)) {
+                        return Err($crate::Error::InvalidCoordinates);
+                    }
+                }
+                self.cfg.map_report(&mut report, caps.as_ref());
+                Ok(report)
+            }
+
+            /// Capabilities for validating and mapping points: fetched if the mapping needs
+            /// them, otherwise whatever is cached.
+            $($async)* fn mapping_capabilities(
+                &mut self,
+            ) -> Result<Option<$crate::Capabilities>, $crate::Error<IFACE::Error>> {
+                if self.cfg.needs_capabilities() {
+                    Ok(Some(self.cached_capabilities()$($await)*?))
+                } else {
+                    Ok(self.caps)
+                }
+            }
+
+            $($async)* fn cached_capabilities(
+                &mut self,
+            ) -> Result<$crate::Capabilities, $crate::Error<IFACE::Error>> {
+                if let Some(caps) = self.caps {
+                    return Ok(caps);
+                }
+                let caps = self.get_capabilities()$($await)*?;
+                self.caps = Some(caps);
+                Ok(caps)
+            }
+
+            $($async)* fn wait_ready(
+                &mut self,
+                delay: &mut impl DelayNs,
+            ) -> Result<(), $crate::Error<IFACE::Error>> {
+                let mut elapsed_ms = 0;
+                loop {
+                    // the controller may NACK while it is still booting, so a bus error only
+                    // counts once the time is up
+                    let err = match self.read_reg8($crate::regs::STATUS)$($await)* {
+                        Ok(status) => {
+                            let decoded = $crate::Status::from_raw(status);
+                            if decoded.is_ready() {
+                                return Ok(());
+                            }
+                            if decoded.state == $crate::DeviceState::Error {
+                                return Err($crate::Error::BadStatus { status });
+                            }
+                            $crate::Error::Timeout { status }
+                        }
+                        Err(err) => err,
+                    };
+                    if elapsed_ms >= self.cfg.init_timeout_ms {
+                        return Err(err);
+                    }
+                    delay.delay_ms($crate::STATUS_POLL_INTERVAL_MS)$($await)*;
+                    elapsed_ms += $crate::STATUS_POLL_INTERVAL_MS;
+                }
+            }
+
+            $($async)* fn read_reg8(&mut self, reg: u8) -> Result<u8, $crate::Error<IFACE::Error>> {
+                let mut buf = [0u8; 1];
+                self.read_regs(reg, &mut buf)$($await)*?;
+                Ok(buf[0])
+            }
+
+            $($async)* fn write_reg8(
+                &mut self,
+                reg: u8,
+                value: u8,
+            ) -> Result<(), $crate::Error<IFACE::Error>> {
+                self.iface.write_reg8(reg, value)$($await)*.map_err($crate::Error::Bus)
+            }
+
+            $($async)* fn read_regs(
+                &mut self,
+                reg: u8,
+                buf: &mut [u8],
+            ) -> Result<(), $crate::Error<IFACE::Error>> {
+                self.iface.read_regs(reg, buf)$($await)*.map_err($crate::Error::Bus)
+            }
+        }
+
+        impl<IFACE, INT, RST> TouchIC<IFACE, INT, RST>
+        where
+            IFACE: Interface,
+            RST: OutputPin,
+        {
+            /// Hard reset the controller (if a reset pin is attached), wait until it is ready
+            /// and cache the capabilities.
+            ///
+            /// Fails with `Error::BadStatus` as soon as the controller reports its error state.
+            /// If it does not reach a ready state (see `Status::is_ready`) within the init
+            /// timeout, fails with `Error::Timeout`, or with the bus error if the last status
+            /// read failed.
+            pub $($async)* fn init(
+                &mut self,
+                delay: &mut impl DelayNs,
+            ) -> Result<(), $crate::Error<IFACE::Error>> {
+                self.hard_reset(delay)$($await)*?;
+                self.wait_ready(delay)$($await)*?;
+                self.caps = Some(self.get_capabilities()$($await)*?);
+                Ok(())
+            }
+
+            /// Pulse `RSTn` low and wait for the controller to boot.
+            ///
+            /// Does nothing if no reset pin is attached.
+            pub $($async)* fn hard_reset(
+                &mut self,
+                delay: &mut impl DelayNs,
+            ) -> Result<(), $crate::Error<IFACE::Error>> {
+                let Some(rst) = self.rst.as_mut() else {
+                    return Ok(());
+                };
+                rst.set_low().map_err(|_| $crate::Error::Pin)?;
+                delay.delay_ms($crate::RESET_PULSE_MS)$($await)*;
+                rst.set_high().map_err(|_| $crate::Error::Pin)?;
+                delay.delay_ms($crate::BOOT_TIME_MS)$($await)*;
+                Ok(())
+            }
+        }
+    };
+}
+
+pub(crate) use driver;
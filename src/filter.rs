@@ -0,0 +1,300 @@
+//! Coordinate jitter filtering.
+//!
+//! Each contact runs through moving average, one-euro low-pass and dead-band stages,
+//! in that order. Every stage can be turned off on its own, and the whole filter can be
+//! bypassed at runtime with `FilterConfig::enabled`.
+
+use core::f32::consts::PI;
+
+use crate::{to_coord, Point};
+
+/// Longest supported moving average window.
+pub const MAX_WINDOW: usize = 8;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct FilterConfig {
+    pub enabled: bool,
+    /// Moving average over the last samples, 1 (or 0) disables, clamped to `MAX_WINDOW`
+    pub average_window: u8,
+    /// Adaptive low-pass, `None` disables
+    pub one_euro: Option<OneEuroConfig>,
+    /// Movement from the last output below this distance is suppressed, 0 disables
+    pub dead_band: u16,
+}
+
+impl Default for FilterConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            average_window: 1,
+            one_euro: None,
+            dead_band: 0,
+        }
+    }
+}
+
+/// One-euro filter parameters: a low-pass whose cutoff rises with speed, so slow
+/// movement is smoothed while fast movement keeps little lag.
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct OneEuroConfig {
+    /// Rate at which samples arrive, in Hz
+    pub rate_hz: f32,
+    /// Cutoff at rest, in Hz. Lower means less jitter.
+    pub min_cutoff_hz: f32,
+    /// Cutoff increase per unit of speed (coordinates per second). Higher means less lag.
+    pub beta: f32,
+    /// Cutoff for the speed estimate, in Hz
+    pub d_cutoff_hz: f32,
+}
+
+impl Default for OneEuroConfig {
+    fn default() -> Self {
+        Self {
+            rate_hz: 100.0,
+            min_cutoff_hz: 1.0,
+            beta: 0.01,
+            d_cutoff_hz: 1.0,
+        }
+    }
+}
+
+/// Filter state for a single contact.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Filter {
+    config: FilterConfig,
+    window: [Point; MAX_WINDOW],
+    window_len: usize,
+    window_next: usize,
+    euro: Option<[OneEuroAxis; 2]>,
+    last: Option<Point>,
+}
+
+impl Filter {
+    pub fn new(config: FilterConfig) -> Self {
+        Self {
+            config,
+            window: [Point { x: 0, y: 0 }; MAX_WINDOW],
+            window_len: 0,
+            window_next: 0,
+            euro: None,
+            last: None,
+        }
+    }
+
+    pub fn config(&self) -> &FilterConfig {
+        &self.config
+    }
+
+    /// Change parameters, keeping state.
+    pub fn set_config(&mut self, config: FilterConfig) {
+        self.config = config;
+    }
+
+    /// Forget history, e.g. when the finger is lifted.
+    pub fn reset(&mut self) {
+        *self = Self::new(self.config);
+    }
+
+    pub fn apply(&mut self, point: Point) -> Point {
+        if !self.config.enabled {
+            self.reset();
+            return point;
+        }
+
+        let mut p = self.average(point);
+        if let Some(config) = self.config.one_euro {
+            p = self.one_euro(p, &config);
+        }
+        let p = self.dead_band(p);
+        self.last = Some(p);
+        p
+    }
+
+    fn average(&mut self, point: Point) -> Point {
+        let window = usize::from(self.config.average_window).clamp(1, MAX_WINDOW);
+        if window == 1 {
+            return point;
+        }
+        self.window[self.window_next % window] = point;
+        self.window_next = (self.window_next + 1) % window;
+        self.window_len = (self.window_len + 1).min(window);
+
+        let (sx, sy) = self.window[..self.window_len]
+            .iter()
+            .fold((0u32, 0u32), |(sx, sy), p| {
+                (sx + u32::from(p.x), sy + u32::from(p.y))
+            });
+        let n = self.window_len as u32;
+        Point {
+            x: ((sx + n / 2) / n) as u16,
+            y: ((sy + n / 2) / n) as u16,
+        }
+    }
+
+    fn one_euro(&mut self, point: Point, config: &OneEuroConfig) -> Point {
+        let (x, y) = (f32::from(point.x), f32::from(point.y));
+        match &mut self.euro {
+            None => {
+                self.euro = Some([OneEuroAxis::new(x), OneEuroAxis::new(y)]);
+                point
+            }
+            Some([ax, ay]) => Point {
+                x: to_coord(ax.apply(x, config)),
+                y: to_coord(ay.apply(y, config)),
+            },
+        }
+    }
+
+    fn dead_band(&self, point: Point) -> Point {
+        match self.last {
+            Some(last) if self.config.dead_band != 0 => {
+                let band = u64::from(self.config.dead_band);
+                if last.distance_sq(point) < band * band {
+                    last
+                } else {
+                    point
+                }
+            }
+            _ => point,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+struct OneEuroAxis {
+    value: f32,
+    speed: f32,
+}
+
+impl OneEuroAxis {
+    fn new(value: f32) -> Self {
+        Self { value, speed: 0.0 }
+    }
+
+    fn apply(&mut self, value: f32, config: &OneEuroConfig) -> f32 {
+        let speed = (value - self.value) * config.rate_hz;
+        self.speed += alpha(config.d_cutoff_hz, config.rate_hz) * (speed - self.speed);
+
+        let abs_speed = if self.speed < 0.0 {
+            -self.speed
+        } else {
+            self.speed
+        };
+        let cutoff = config.min_cutoff_hz + config.beta * abs_speed;
+        self.value += alpha(cutoff, config.rate_hz) * (value - self.value);
+        self.value
+    }
+}
+
+/// Smoothing factor of a first order low-pass with `cutoff_hz`, sampled at `rate_hz`.
+fn alpha(cutoff_hz: f32, rate_hz: f32) -> f32 {
+    1.0 / (1.0 + rate_hz / (2.0 * PI * cutoff_hz))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn p(x: u16, y: u16) -> Point {
+        Point { x, y }
+    }
+
+    fn filter(average_window: u8, one_euro: Option<OneEuroConfig>, dead_band: u16) -> Filter {
+        Filter::new(FilterConfig {
+            enabled: true,
+            average_window,
+            one_euro,
+            dead_band,
+        })
+    }
+
+    #[test]
+    fn default_passes_through() {
+        let mut f = Filter::new(FilterConfig::default());
+        assert_eq!(f.apply(p(10, 20)), p(10, 20));
+        assert_eq!(f.apply(p(13, 17)), p(13, 17));
+    }
+
+    #[test]
+    fn average_fills_window_and_rounds() {
+        let mut f = filter(3, None, 0);
+        assert_eq!(f.apply(p(0, 10)), p(0, 10));
+        // half rounds up
+        assert_eq!(f.apply(p(1, 11)), p(1, 11));
+        assert_eq!(f.apply(p(5, 15)), p(2, 12));
+        // window full, drops the oldest sample
+        assert_eq!(f.apply(p(8, 18)), p(5, 15));
+    }
+
+    #[test]
+    fn average_window_is_clamped() {
+        let mut f = filter(u8::MAX, None, 0);
+        for _ in 0..MAX_WINDOW {
+            f.apply(p(0, 0));
+        }
+        assert_eq!(f.apply(p(800, 800)), p(100, 100));
+    }
+
+    #[test]
+    fn dead_band_holds_and_releases() {
+        let mut f = filter(1, None, 10);
+        assert_eq!(f.apply(p(100, 100)), p(100, 100));
+        assert_eq!(f.apply(p(105, 105)), p(100, 100));
+        // measured from the last output, so slow drift does not creep through
+        assert_eq!(f.apply(p(107, 107)), p(100, 100));
+        assert_eq!(f.apply(p(100, 110)), p(100, 110));
+    }
+
+    #[test]
+    fn dead_band_full_range() {
+        let mut f = filter(1, None, u16::MAX);
+        assert_eq!(f.apply(p(0, 0)), p(0, 0));
+        assert_eq!(f.apply(p(40000, 40000)), p(0, 0));
+        // squared distance beyond u32
+        assert_eq!(f.apply(p(u16::MAX, u16::MAX)), p(u16::MAX, u16::MAX));
+    }
+
+    #[test]
+    fn one_euro_converges_to_step() {
+        let mut f = filter(1, Some(OneEuroConfig::default()), 0);
+        assert_eq!(f.apply(p(0, 500)), p(0, 500));
+
+        let mut last = f.apply(p(1000, 500));
+        assert!(last.x > 0 && last.x < 1000, "{last:?}");
+        assert_eq!(last.y, 500);
+        for _ in 0..100 {
+            let next = f.apply(p(1000, 500));
+            assert!(next.x >= last.x, "{next:?} after {last:?}");
+            last = next;
+        }
+        assert_eq!(last, p(1000, 500));
+    }
+
+    #[test]
+    fn disabled_bypasses_and_resets() {
+        let mut f = filter(2, None, 0);
+        f.apply(p(0, 0));
+        assert_eq!(f.apply(p(10, 10)), p(5, 5));
+
+        let config = *f.config();
+        f.set_config(FilterConfig {
+            enabled: false,
+            ..config
+        });
+        assert_eq!(f.apply(p(50, 50)), p(50, 50));
+
+        f.set_config(config);
+        // no history from before bypassing
+        assert_eq!(f.apply(p(20, 20)), p(20, 20));
+    }
+
+    #[test]
+    fn reset_forgets_history() {
+        let mut f = filter(2, None, 10);
+        f.apply(p(0, 0));
+        f.reset();
+        assert_eq!(f.apply(p(6, 6)), p(6, 6));
+    }
+}
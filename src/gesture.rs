@@ -0,0 +1,655 @@
+//! Software gesture recognition from tracked fingers.
+//!
+//! Works on any firmware, unlike `GestureType`, and reports gesture parameters. Feed it
+//! the `TouchEvent`s from a `Tracker` along with a host timestamp in milliseconds, and
+//! call `poll` regularly so long presses are detected while the finger does not move.
+
+use core::f32::consts::{FRAC_PI_2, PI};
+
+use crate::tracker::TouchEvent;
+use crate::{to_coord, Point};
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct GestureConfig {
+    /// Longest press that still counts as a tap
+    pub tap_max_ms: u32,
+    /// Furthest a finger may travel during a tap or long press
+    pub tap_max_distance: u16,
+    /// Longest time from the end of a tap to the end of the next one for a double tap
+    pub double_tap_max_ms: u32,
+    /// Furthest apart the two taps of a double tap may be
+    pub double_tap_max_distance: u16,
+    /// Time a finger must be held still for a long press
+    pub long_press_ms: u32,
+    /// Shortest travel for a swipe
+    pub swipe_min_distance: u16,
+    /// Lowest average speed for a swipe, in coordinates per second
+    pub swipe_min_velocity: f32,
+    /// Change in finger distance before a pinch is reported
+    pub pinch_min_distance: u16,
+    /// Rotation, in degrees, before a rotate is reported
+    pub rotate_min_degrees: f32,
+}
+
+impl Default for GestureConfig {
+    fn default() -> Self {
+        Self {
+            tap_max_ms: 250,
+            tap_max_distance: 20,
+            double_tap_max_ms: 300,
+            double_tap_max_distance: 40,
+            long_press_ms: 600,
+            swipe_min_distance: 80,
+            swipe_min_velocity: 200.0,
+            pinch_min_distance: 20,
+            rotate_min_degrees: 10.0,
+        }
+    }
+}
+
+/// Direction on the display, with y growing downwards.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SwipeDirection {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Gesture {
+    Tap {
+        point: Point,
+    },
+    /// Second tap of a pair, reported instead of its `Tap`
+    DoubleTap {
+        point: Point,
+    },
+    LongPress {
+        point: Point,
+    },
+    Swipe {
+        direction: SwipeDirection,
+        /// Travel from touch down to lift off
+        distance: u16,
+        /// Average speed, in coordinates per second
+        velocity: f32,
+    },
+    /// Reported on every move once started, `scale` is relative to the finger distance
+    /// when the second finger went down
+    Pinch {
+        center: Point,
+        scale: f32,
+    },
+    /// Reported on every move once started, `degrees` is clockwise on the display and
+    /// relative to when the second finger went down
+    Rotate {
+        center: Point,
+        degrees: f32,
+    },
+}
+
+/// Gestures produced by one `GestureRecognizer::feed`.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Gestures {
+    gestures: [Option<Gesture>; 2],
+    pos: usize,
+}
+
+impl Gestures {
+    fn new() -> Self {
+        Self {
+            gestures: [None; 2],
+            pos: 0,
+        }
+    }
+
+    fn push(&mut self, gesture: Gesture) {
+        if let Some(slot) = self.gestures.iter_mut().find(|slot| slot.is_none()) {
+            *slot = Some(gesture);
+        }
+    }
+}
+
+impl Iterator for Gestures {
+    type Item = Gesture;
+
+    fn next(&mut self) -> Option<Gesture> {
+        let gesture = *self.gestures.get(self.pos)?;
+        self.pos += 1;
+        gesture
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+struct Contact {
+    id: u8,
+    start: Point,
+    point: Point,
+    down_ms: u32,
+    /// Travelled further than a tap allows
+    moved: bool,
+    long_pressed: bool,
+}
+
+/// Finger vector and distance when the second finger went down.
+#[derive(Copy, Clone, PartialEq, Debug)]
+struct TwoFingerStart {
+    dx: f32,
+    dy: f32,
+    span: f32,
+}
+
+/// Recognizes gestures made by the first two fingers of a touch sequence.
+///
+/// Once a second finger goes down, single finger gestures are suppressed until all fingers
+/// are lifted.
+#[derive(Clone, Debug)]
+pub struct GestureRecognizer {
+    config: GestureConfig,
+    contacts: [Option<Contact>; 2],
+    multi: bool,
+    two: Option<TwoFingerStart>,
+    pinching: bool,
+    rotating: bool,
+    last_tap: Option<(Point, u32)>,
+}
+
+impl Default for GestureRecognizer {
+    fn default() -> Self {
+        Self::new(GestureConfig::default())
+    }
+}
+
+impl GestureRecognizer {
+    pub fn new(config: GestureConfig) -> Self {
+        Self {
+            config,
+            contacts: [None; 2],
+            multi: false,
+            two: None,
+            pinching: false,
+            rotating: false,
+            last_tap: None,
+        }
+    }
+
+    pub fn config(&self) -> &GestureConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: GestureConfig) {
+        self.config = config;
+    }
+
+    /// Feed one event from `Tracker::update`, with the host time in milliseconds.
+    ///
+    /// The clock may wrap around.
+    pub fn feed(&mut self, event: TouchEvent, now_ms: u32) -> Gestures {
+        let mut gestures = Gestures::new();
+        match event {
+            TouchEvent::Down { id, point } => self.down(id, point, now_ms),
+            TouchEvent::Move { id, point } => {
+                let tap_max_distance = f32::from(self.config.tap_max_distance);
+                if let Some(contact) = self.contact_mut(id) {
+                    contact.point = point;
+                    if distance(contact.start, point) > tap_max_distance {
+                        contact.moved = true;
+                    }
+                    self.two_finger(&mut gestures);
+                }
+            }
+            TouchEvent::Up { id, point } => {
+                if let Some(gesture) = self.up(id, point, now_ms) {
+                    gestures.push(gesture);
+                }
+            }
+        }
+        gestures
+    }
+
+    /// Check for a long press, call regularly while fingers are down.
+    pub fn poll(&mut self, now_ms: u32) -> Option<Gesture> {
+        if self.multi {
+            return None;
+        }
+        let long_press_ms = self.config.long_press_ms;
+        let contact = self.contacts.iter_mut().flatten().next()?;
+        if contact.moved
+            || contact.long_pressed
+            || now_ms.wrapping_sub(contact.down_ms) < long_press_ms
+        {
+            return None;
+        }
+        contact.long_pressed = true;
+        Some(Gesture::LongPress {
+            point: contact.point,
+        })
+    }
+
+    /// Forget all fingers, e.g. after `Tracker::reset`.
+    pub fn reset(&mut self) {
+        *self = Self::new(self.config);
+    }
+
+    fn down(&mut self, id: u8, point: Point, now_ms: u32) {
+        if self.contacts.iter().all(Option::is_none) {
+            self.multi = false;
+        }
+        let contact = Contact {
+            id,
+            start: point,
+            point,
+            down_ms: now_ms,
+            moved: false,
+            long_pressed: false,
+        };
+        match self.contacts.iter_mut().find(|slot| slot.is_none()) {
+            Some(slot) => *slot = Some(contact),
+            None => self.multi = true,
+        }
+
+        if let [Some(a), Some(b)] = self.contacts {
+            self.multi = true;
+            let (dx, dy) = vector(a.point, b.point);
+            self.two = Some(TwoFingerStart {
+                dx,
+                dy,
+                span: sqrt(dx * dx + dy * dy),
+            });
+            self.pinching = false;
+            self.rotating = false;
+        }
+    }
+
+    fn up(&mut self, id: u8, point: Point, now_ms: u32) -> Option<Gesture> {
+        let slot = self
+            .contacts
+            .iter_mut()
+            .find(|slot| slot.is_some_and(|contact| contact.id == id))?;
+        let contact = slot.take()?;
+        self.two = None;
+        if self.multi || contact.long_pressed {
+            return None;
+        }
+
+        let duration_ms = now_ms.wrapping_sub(contact.down_ms);
+        let travel = distance(contact.start, point);
+        let velocity = travel * 1000.0 / duration_ms.max(1) as f32;
+
+        if travel >= f32::from(self.config.swipe_min_distance)
+            && velocity >= self.config.swipe_min_velocity
+        {
+            self.last_tap = None;
+            let (dx, dy) = vector(contact.start, point);
+            let horizontal = abs(dx) >= abs(dy);
+            let direction = match (horizontal, dx < 0.0, dy < 0.0) {
+                (true, true, _) => SwipeDirection::Left,
+                (true, false, _) => SwipeDirection::Right,
+                (false, _, true) => SwipeDirection::Up,
+                (false, _, false) => SwipeDirection::Down,
+            };
+            return Some(Gesture::Swipe {
+                direction,
+                distance: to_coord(travel),
+                velocity,
+            });
+        }
+
+        if contact.moved || duration_ms > self.config.tap_max_ms {
+            return None;
+        }
+        match self.last_tap.take() {
+            Some((last, last_ms))
+                if now_ms.wrapping_sub(last_ms) <= self.config.double_tap_max_ms
+                    && distance(last, point) <= f32::from(self.config.double_tap_max_distance) =>
+            {
+                Some(Gesture::DoubleTap { point })
+            }
+            _ => {
+                self.last_tap = Some((point, now_ms));
+                Some(Gesture::Tap { point })
+            }
+        }
+    }
+
+    fn two_finger(&mut self, gestures: &mut Gestures) {
+        let (Some(start), [Some(a), Some(b)]) = (self.two, self.contacts) else {
+            return;
+        };
+        let center = Point {
+            x: ((u32::from(a.point.x) + u32::from(b.point.x)) / 2) as u16,
+            y: ((u32::from(a.point.y) + u32::from(b.point.y)) / 2) as u16,
+        };
+        let (dx, dy) = vector(a.point, b.point);
+
+        let span = sqrt(dx * dx + dy * dy);
+        if start.span > 0.0
+            && (self.pinching
+                || abs(span - start.span) >= f32::from(self.config.pinch_min_distance))
+        {
+            self.pinching = true;
+            gestures.push(Gesture::Pinch {
+                center,
+                scale: span / start.span,
+            });
+        }
+
+        let cross = start.dx * dy - start.dy * dx;
+        let dot = start.dx * dx + start.dy * dy;
+        let degrees = atan2(cross, dot) * 180.0 / PI;
+        if self.rotating || abs(degrees) >= self.config.rotate_min_degrees {
+            self.rotating = true;
+            gestures.push(Gesture::Rotate { center, degrees });
+        }
+    }
+
+    fn contact_mut(&mut self, id: u8) -> Option<&mut Contact> {
+        self.contacts
+            .iter_mut()
+            .flatten()
+            .find(|contact| contact.id == id)
+    }
+}
+
+fn vector(from: Point, to: Point) -> (f32, f32) {
+    (
+        f32::from(to.x) - f32::from(from.x),
+        f32::from(to.y) - f32::from(from.y),
+    )
+}
+
+fn distance(a: Point, b: Point) -> f32 {
+    let (dx, dy) = vector(a, b);
+    sqrt(dx * dx + dy * dy)
+}
+
+fn abs(v: f32) -> f32 {
+    if v < 0.0 {
+        -v
+    } else {
+        v
+    }
+}
+
+/// Square root without `libm`, Newton iterations from a bit-level estimate.
+fn sqrt(v: f32) -> f32 {
+    if v <= 0.0 {
+        return 0.0;
+    }
+    let mut r = f32::from_bits((v.to_bits() >> 1) + 0x1fbd_1df5);
+    for _ in 0..3 {
+        r = 0.5 * (r + v / r);
+    }
+    r
+}
+
+/// Four-quadrant arctangent without `libm`, error below 0.02 degrees.
+fn atan2(y: f32, x: f32) -> f32 {
+    let (ax, ay) = (abs(x), abs(y));
+    if ax == 0.0 && ay == 0.0 {
+        return 0.0;
+    }
+    let a = if ax >= ay { ay / ax } else { ax / ay };
+    let s = a * a;
+    let mut r = ((-0.046_496_475 * s + 0.159_314_22) * s - 0.327_622_76) * s * a + a;
+    if ay > ax {
+        r = FRAC_PI_2 - r;
+    }
+    if x < 0.0 {
+        r = PI - r;
+    }
+    if y < 0.0 {
+        r = -r;
+    }
+    r
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn p(x: u16, y: u16) -> Point {
+        Point { x, y }
+    }
+
+    fn down(id: u8, point: Point) -> TouchEvent {
+        TouchEvent::Down { id, point }
+    }
+
+    fn moved(id: u8, point: Point) -> TouchEvent {
+        TouchEvent::Move { id, point }
+    }
+
+    fn up(id: u8, point: Point) -> TouchEvent {
+        TouchEvent::Up { id, point }
+    }
+
+    /// Feed an event expecting at most one gesture.
+    fn feed(g: &mut GestureRecognizer, event: TouchEvent, now_ms: u32) -> Option<Gesture> {
+        let mut gestures = g.feed(event, now_ms);
+        let gesture = gestures.next();
+        assert_eq!(gestures.next(), None);
+        gesture
+    }
+
+    fn assert_approx(a: f32, b: f32, tolerance: f32) {
+        assert!(abs(a - b) <= tolerance, "{a} != {b}");
+    }
+
+    #[test]
+    fn tap_and_double_tap() {
+        let mut g = GestureRecognizer::default();
+        assert_eq!(feed(&mut g, down(0, p(100, 100)), 0), None);
+        assert_eq!(
+            feed(&mut g, up(0, p(105, 102)), 100),
+            Some(Gesture::Tap { point: p(105, 102) })
+        );
+
+        feed(&mut g, down(1, p(110, 100)), 200);
+        assert_eq!(
+            feed(&mut g, up(1, p(110, 100)), 250),
+            Some(Gesture::DoubleTap { point: p(110, 100) })
+        );
+
+        // a third tap starts a new pair
+        feed(&mut g, down(2, p(110, 100)), 300);
+        assert_eq!(
+            feed(&mut g, up(2, p(110, 100)), 350),
+            Some(Gesture::Tap { point: p(110, 100) })
+        );
+    }
+
+    #[test]
+    fn tap_thresholds() {
+        let config = GestureConfig::default();
+        let mut g = GestureRecognizer::new(config);
+
+        // held too long
+        feed(&mut g, down(0, p(100, 100)), 0);
+        assert_eq!(
+            feed(&mut g, up(0, p(100, 100)), config.tap_max_ms + 1),
+            None
+        );
+
+        // moved too far, but too short for a swipe
+        feed(&mut g, down(1, p(100, 100)), 1000);
+        feed(&mut g, moved(1, p(140, 100)), 1050);
+        assert_eq!(feed(&mut g, up(1, p(100, 100)), 1100), None);
+
+        // second tap too late or too far away for a double tap
+        feed(&mut g, down(2, p(100, 100)), 2000);
+        assert!(matches!(
+            feed(&mut g, up(2, p(100, 100)), 2050),
+            Some(Gesture::Tap { .. })
+        ));
+        feed(&mut g, down(3, p(100, 100)), 2500);
+        assert!(matches!(
+            feed(&mut g, up(3, p(100, 100)), 2550),
+            Some(Gesture::Tap { .. })
+        ));
+        feed(&mut g, down(4, p(300, 100)), 2600);
+        assert!(matches!(
+            feed(&mut g, up(4, p(300, 100)), 2650),
+            Some(Gesture::Tap { .. })
+        ));
+    }
+
+    #[test]
+    fn long_press_then_no_tap() {
+        let mut g = GestureRecognizer::default();
+        feed(&mut g, down(0, p(100, 100)), 0);
+        assert_eq!(g.poll(599), None);
+        assert_eq!(g.poll(600), Some(Gesture::LongPress { point: p(100, 100) }));
+        // reported once
+        assert_eq!(g.poll(700), None);
+        assert_eq!(feed(&mut g, up(0, p(100, 100)), 800), None);
+    }
+
+    #[test]
+    fn no_long_press_after_moving() {
+        let mut g = GestureRecognizer::default();
+        feed(&mut g, down(0, p(100, 100)), 0);
+        feed(&mut g, moved(0, p(150, 100)), 100);
+        assert_eq!(g.poll(1000), None);
+    }
+
+    #[test]
+    fn swipe_direction_and_velocity() {
+        let cases = [
+            (p(500, 300), SwipeDirection::Right),
+            (p(100, 300), SwipeDirection::Left),
+            (p(300, 100), SwipeDirection::Up),
+            (p(300, 500), SwipeDirection::Down),
+        ];
+        let mut g = GestureRecognizer::default();
+        for (i, (end, direction)) in cases.into_iter().enumerate() {
+            let t = 1000 * i as u32;
+            feed(&mut g, down(0, p(300, 300)), t);
+            let gesture = feed(&mut g, up(0, end), t + 100);
+            let Some(Gesture::Swipe {
+                direction: d,
+                distance,
+                velocity,
+            }) = gesture
+            else {
+                panic!("{gesture:?}");
+            };
+            assert_eq!(d, direction);
+            assert_eq!(distance, 200);
+            assert_approx(velocity, 2000.0, 1.0);
+        }
+    }
+
+    #[test]
+    fn slow_swipe_is_ignored() {
+        let mut g = GestureRecognizer::default();
+        feed(&mut g, down(0, p(300, 300)), 0);
+        // 200 units in 2 s, below the default 200 units/s
+        assert_eq!(feed(&mut g, up(0, p(300, 490)), 2000), None);
+    }
+
+    #[test]
+    fn pinch_scale_and_rotate_sign() {
+        let mut g = GestureRecognizer::default();
+        feed(&mut g, down(0, p(100, 100)), 0);
+        feed(&mut g, down(1, p(200, 100)), 0);
+
+        // spread along the same axis: pinch only
+        assert_eq!(
+            feed(&mut g, moved(1, p(300, 100)), 50),
+            Some(Gesture::Pinch {
+                center: p(200, 100),
+                scale: 2.0
+            })
+        );
+
+        // quarter turn clockwise on the display (y grows downwards), same distance
+        let mut gestures = g.feed(moved(1, p(100, 300)), 100);
+        let Some(Gesture::Pinch { scale, .. }) = gestures.next() else {
+            panic!();
+        };
+        assert_approx(scale, 2.0, 1e-3);
+        let Some(Gesture::Rotate { center, degrees }) = gestures.next() else {
+            panic!();
+        };
+        assert_eq!(center, p(100, 200));
+        assert_approx(degrees, 90.0, 0.02);
+
+        // counter-clockwise is negative
+        let mut gestures = g.feed(moved(1, p(100, 0)), 150);
+        gestures.next();
+        let Some(Gesture::Rotate { degrees, .. }) = gestures.next() else {
+            panic!();
+        };
+        assert_approx(degrees, -90.0, 0.02);
+
+        // no single finger gestures for the rest of the sequence
+        assert_eq!(feed(&mut g, up(1, p(100, 0)), 200), None);
+        assert_eq!(feed(&mut g, up(0, p(100, 100)), 210), None);
+    }
+
+    #[test]
+    fn small_two_finger_moves_are_ignored() {
+        let mut g = GestureRecognizer::default();
+        feed(&mut g, down(0, p(100, 100)), 0);
+        feed(&mut g, down(1, p(200, 100)), 0);
+        assert_eq!(feed(&mut g, moved(1, p(210, 105)), 50), None);
+    }
+
+    #[test]
+    fn clock_wraps_around() {
+        let mut g = GestureRecognizer::default();
+        feed(&mut g, down(0, p(100, 100)), u32::MAX - 50);
+        assert_eq!(
+            feed(&mut g, up(0, p(100, 100)), 49),
+            Some(Gesture::Tap { point: p(100, 100) })
+        );
+        feed(&mut g, down(1, p(100, 100)), 100);
+        assert_eq!(
+            feed(&mut g, up(1, p(100, 100)), 150),
+            Some(Gesture::DoubleTap { point: p(100, 100) })
+        );
+
+        feed(&mut g, down(2, p(100, 100)), u32::MAX - 100);
+        assert_eq!(g.poll(u32::MAX), None);
+        assert!(g.poll(499).is_some());
+    }
+
+    #[test]
+    fn sqrt_accuracy() {
+        for (v, expected) in [
+            (0.0, 0.0),
+            (1.0, 1.0),
+            (2.0, core::f32::consts::SQRT_2),
+            (0.25, 0.5),
+            (10_000.0, 100.0),
+            (8_589_672_450.0, 92_680.49),
+        ] {
+            assert_approx(sqrt(v), expected, expected * 1e-5);
+        }
+    }
+
+    #[test]
+    fn atan2_accuracy() {
+        let tolerance = 0.02 * PI / 180.0;
+        for (y, x, expected) in [
+            (0.0, 1.0, 0.0),
+            (1.0, 1.0, PI / 4.0),
+            (1.0, 0.0, FRAC_PI_2),
+            (1.0, -1.0, 3.0 * PI / 4.0),
+            (0.0, -1.0, PI),
+            (-1.0, -1.0, -3.0 * PI / 4.0),
+            (-1.0, 0.0, -FRAC_PI_2),
+            (-1.0, 1.0, -PI / 4.0),
+            (1.0, 1.732_050_8, PI / 6.0),
+            (1.732_050_8, 1.0, PI / 3.0),
+            (0.0, 0.0, 0.0),
+        ] {
+            assert_approx(atan2(y, x), expected, tolerance);
+        }
+    }
+}
@@ -0,0 +1,190 @@
+//! Register access over I2C or SPI.
+
+use embedded_hal_1::i2c::I2c;
+use embedded_hal_1::spi::{Operation, SpiDevice};
+
+/// SPI command byte for a register write.
+pub(crate) const SPI_CMD_WRITE: u8 = 0x00;
+/// SPI command byte for a register read.
+pub(crate) const SPI_CMD_READ: u8 = 0x01;
+/// Turnaround byte clocked out between the read header and the first data byte.
+pub(crate) const SPI_DUMMY: u8 = 0x00;
+
+/// Register level access to the controller.
+pub trait Interface {
+    type Error;
+
+    /// Read consecutive registers starting at `reg`.
+    fn read_regs(&mut self, reg: u8, buf: &mut [u8]) -> Result<(), Self::Error>;
+
+    fn write_reg8(&mut self, reg: u8, value: u8) -> Result<(), Self::Error>;
+}
+
+pub struct I2cInterface<I2C> {
+    pub(crate) i2c: I2C,
+    pub(crate) addr: u8,
+}
+
+impl<I2C> I2cInterface<I2C> {
+    pub fn new(i2c: I2C, addr: u8) -> Self {
+        Self { i2c, addr }
+    }
+}
+
+impl<I2C> Interface for I2cInterface<I2C>
+where
+    I2C: I2c,
+{
+    type Error = I2C::Error;
+
+    fn read_regs(&mut self, reg: u8, buf: &mut [u8]) -> Result<(), Self::Error> {
+        self.i2c.write_read(self.addr, &[reg], buf)
+    }
+
+    fn write_reg8(&mut self, reg: u8, value: u8) -> Result<(), Self::Error> {
+        self.i2c.write(self.addr, &[reg, value])
+    }
+}
+
+/// I2C bus from embedded-hal 0.2, for board support crates that only offer
+/// `blocking::i2c::{WriteRead, Write}`.
+#[cfg(feature = "eh02")]
+pub struct I2c02Interface<I2C> {
+    pub(crate) i2c: I2C,
+    pub(crate) addr: u8,
+}
+
+#[cfg(feature = "eh02")]
+impl<I2C> I2c02Interface<I2C> {
+    pub fn new(i2c: I2C, addr: u8) -> Self {
+        Self { i2c, addr }
+    }
+}
+
+#[cfg(feature = "eh02")]
+impl<I2C, E> Interface for I2c02Interface<I2C>
+where
+    I2C: embedded_hal_02::blocking::i2c::WriteRead<Error = E>
+        + embedded_hal_02::blocking::i2c::Write<Error = E>,
+{
+    type Error = E;
+
+    fn read_regs(&mut self, reg: u8, buf: &mut [u8]) -> Result<(), Self::Error> {
+        self.i2c.write_read(self.addr, &[reg], buf)
+    }
+
+    fn write_reg8(&mut self, reg: u8, value: u8) -> Result<(), Self::Error> {
+        self.i2c.write(self.addr, &[reg, value])
+    }
+}
+
+/// Sitronix SPI framing.
+///
+/// Every transfer starts with a command byte (read or write) and the register address.
+/// Reads then clock out one dummy byte before the register data follows. As described for
+/// the SPI interface in the Sitronix Touch IC Touch Screen Controller I2C & SPI Interface
+/// Protocol A, see Ref in the README.
+pub struct SpiInterface<SPI> {
+    pub(crate) spi: SPI,
+}
+
+impl<SPI> SpiInterface<SPI> {
+    pub fn new(spi: SPI) -> Self {
+        Self { spi }
+    }
+}
+
+impl<SPI> Interface for SpiInterface<SPI>
+where
+    SPI: SpiDevice,
+{
+    type Error = SPI::Error;
+
+    fn read_regs(&mut self, reg: u8, buf: &mut [u8]) -> Result<(), Self::Error> {
+        self.spi.transaction(&mut [
+            Operation::Write(&[SPI_CMD_READ, reg, SPI_DUMMY]),
+            Operation::Read(buf),
+        ])
+    }
+
+    fn write_reg8(&mut self, reg: u8, value: u8) -> Result<(), Self::Error> {
+        self.spi.write(&[SPI_CMD_WRITE, reg, value])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::convert::Infallible;
+
+    use embedded_hal_1::spi::ErrorType;
+
+    use super::*;
+
+    #[derive(Copy, Clone, PartialEq, Debug)]
+    enum Recorded {
+        Write([u8; 3]),
+        Read(usize),
+    }
+
+    /// SPI device logging the operations of a single transaction.
+    #[derive(Default)]
+    struct Recorder {
+        ops: [Option<Recorded>; 4],
+        transactions: usize,
+    }
+
+    impl ErrorType for Recorder {
+        type Error = Infallible;
+    }
+
+    impl SpiDevice for Recorder {
+        fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Infallible> {
+            self.transactions += 1;
+            self.ops = [None; 4];
+            for (op, recorded) in operations.iter_mut().zip(&mut self.ops) {
+                *recorded = Some(match op {
+                    Operation::Write(bytes) => Recorded::Write((*bytes).try_into().unwrap()),
+                    Operation::Read(buf) => {
+                        for (i, b) in buf.iter_mut().enumerate() {
+                            *b = 0xA0 + i as u8;
+                        }
+                        Recorded::Read(buf.len())
+                    }
+                    _ => panic!("unexpected operation"),
+                });
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn spi_read_framing() {
+        let mut iface = SpiInterface::new(Recorder::default());
+        let mut buf = [0u8; 2];
+        iface.read_regs(0x12, &mut buf).unwrap();
+
+        assert_eq!(iface.spi.transactions, 1);
+        assert_eq!(
+            iface.spi.ops,
+            [
+                Some(Recorded::Write([0x01, 0x12, 0x00])),
+                Some(Recorded::Read(2)),
+                None,
+                None,
+            ]
+        );
+        assert_eq!(buf, [0xA0, 0xA1]);
+    }
+
+    #[test]
+    fn spi_write_framing() {
+        let mut iface = SpiInterface::new(Recorder::default());
+        iface.write_reg8(0x02, 0x7E).unwrap();
+
+        assert_eq!(iface.spi.transactions, 1);
+        assert_eq!(
+            iface.spi.ops,
+            [Some(Recorded::Write([0x00, 0x02, 0x7E])), None, None, None]
+        );
+    }
+}
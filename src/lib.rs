@@ -1,13 +1,57 @@
 #![no_std]
 
-use embedded_hal_1::i2c::I2c;
+use core::convert::Infallible;
+use core::time::Duration;
+
+use embedded_hal_1::delay::DelayNs;
+use embedded_hal_1::digital::{ErrorType, InputPin, OutputPin};
+use embedded_hal_1::i2c::{self, I2c};
+use embedded_hal_1::spi::{self, SpiDevice};
+
+use crate::calibration::Calibration;
+use crate::interface::Interface;
+
+#[cfg(feature = "async")]
+pub mod asynch;
+pub mod calibration;
+pub mod filter;
+pub mod gesture;
+pub mod interface;
+pub mod scan_rate;
+pub mod tracker;
+
+mod driver;
+mod model;
+mod transform;
+
+pub use model::ChipModel;
+pub use transform::{Rotation, Transform};
 
 pub const DEFAULT_ADDR: u8 = 0x55;
 
+/// Maximum number of contact slots in a touch report, over all chip models.
+pub const MAX_CONTACTS: usize = 10;
+/// Longest touch report burst over all chip models.
+pub(crate) const REPORT_LEN: usize = model::COUNTER_PREFIX_LEN
+    + (regs::XY_COORDINATES - regs::ADVANCED_TOUCH_INFO) as usize
+    + 4 * MAX_CONTACTS;
+
+/// `RSTn` low pulse width.
+pub(crate) const RESET_PULSE_MS: u32 = 10;
+/// Time from `RSTn` release until the controller answers on the bus.
+pub(crate) const BOOT_TIME_MS: u32 = 100;
+/// Default time budget for `init` to see the controller ready.
+pub const DEFAULT_INIT_TIMEOUT_MS: u32 = 1000;
+pub(crate) const STATUS_POLL_INTERVAL_MS: u32 = 1;
+
 pub mod regs {
+    pub const FIRMWARE_VERSION: u8 = 0x00;
     pub const STATUS: u8 = 0x01;
+    pub const DEVICE_CONTROL: u8 = 0x02;
+    pub const TIMEOUT_TO_IDLE: u8 = 0x03;
     pub const CONTACT_COUNT_MAX: u8 = 0x3F;
     pub const MISC_INFO: u8 = 0xF0;
+    pub const CHIP_ID: u8 = 0xF4;
 
     pub const XY_RESOLUTION_H: u8 = 0x04;
     pub const X_RESOLUTION_L: u8 = 0x05;
@@ -16,108 +60,210 @@ pub mod regs {
     pub const SENSING_COUNTER_L: u8 = 0x07;
     pub const SENSING_COUNTER_H: u8 = 0x08;
 
+    /// Firmware Revision 3..0, most significant byte first
+    pub const FIRMWARE_REVISION: u8 = 0x0C;
+
     pub const ADVANCED_TOUCH_INFO: u8 = 0x10;
+    pub const KEYS: u8 = 0x11;
+    pub const XY_COORDINATES: u8 = 0x12;
 }
 
-pub struct TouchIC<I2C> {
-    i2c: I2C,
-    addr: u8,
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<E> {
+    /// Underlying bus error
+    Bus(E),
+    /// `INTn` or `RSTn` pin error
+    Pin,
+    /// Controller did not become ready in time, with the last status register value.
+    /// Decode it with `Status::from_raw`.
+    Timeout { status: u8 },
+    /// Controller reported an error state, with the raw status register value.
+    /// Decode it with `Status::from_raw`.
+    BadStatus { status: u8 },
+    /// Reported coordinates are outside of the sensor resolution
+    InvalidCoordinates,
+    /// Device is not a supported Sitronix controller
+    UnsupportedChip,
+    /// Operation is not supported by the selected chip model
+    Unsupported,
 }
 
-impl<I2C> TouchIC<I2C>
+impl<E> i2c::Error for Error<E>
 where
-    I2C: I2c,
+    E: i2c::Error,
 {
-    pub fn new(i2c: I2C, addr: u8) -> Self {
-        Self { i2c, addr }
+    fn kind(&self) -> i2c::ErrorKind {
+        match self {
+            Error::Bus(e) => e.kind(),
+            _ => i2c::ErrorKind::Other,
+        }
     }
+}
 
-    pub fn new_default(i2c: I2C) -> Self {
-        Self::new(i2c, DEFAULT_ADDR)
+impl<E> spi::Error for Error<E>
+where
+    E: spi::Error,
+{
+    fn kind(&self) -> spi::ErrorKind {
+        match self {
+            Error::Bus(e) => e.kind(),
+            _ => spi::ErrorKind::Other,
+        }
     }
+}
 
-    pub fn init(&mut self) -> Result<(), I2C::Error> {
-        self.wait_normal_status()?;
-        Ok(())
-    }
+/// Driver settings, shared by the blocking and async drivers.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Config {
+    pub(crate) init_timeout_ms: u32,
+    pub(crate) model: ChipModel,
+    pub(crate) transform: Transform,
+    /// Display width and height to rescale coordinates to
+    pub(crate) display_size: Option<(u16, u16)>,
+    pub(crate) calibration: Option<Calibration>,
+}
 
-    pub fn get_gesture_info(&mut self) -> Result<GestureInfo, I2C::Error> {
-        let raw = self.read_reg8(regs::ADVANCED_TOUCH_INFO)?;
-        Ok(GestureInfo {
-            gesture_type: GestureType::from_u8(raw),
-            proximity: raw & 0b0100_0000 != 0,
-            water: raw & 0b0010_0000 != 0,
-        })
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            init_timeout_ms: DEFAULT_INIT_TIMEOUT_MS,
+            model: ChipModel::default(),
+            transform: Transform::IDENTITY,
+            display_size: None,
+            calibration: None,
+        }
     }
+}
 
-    pub fn get_point0(&mut self) -> Result<Option<Point>, I2C::Error> {
-        self.get_point(0)
+impl Config {
+    /// Whether `map_report` needs the sensor capabilities.
+    pub(crate) fn needs_capabilities(&self) -> bool {
+        !self.transform.is_identity() || self.display_size.is_some()
     }
 
-    pub fn get_point1(&mut self) -> Result<Option<Point>, I2C::Error> {
-        self.get_point(1)
+    /// Map contacts from sensor to display coordinates, see `map_point`.
+    pub(crate) fn map_report(&self, report: &mut TouchReport, caps: Option<&Capabilities>) {
+        if !self.needs_capabilities() && self.calibration.is_none() {
+            return;
+        }
+        report.map_points(|p| self.map_point(p, caps));
     }
 
-    pub fn get_point(&mut self, nth: u8) -> Result<Option<Point>, I2C::Error> {
-        if nth > 9 {
-            return Ok(None); // max 10 points
+    /// Map a point from sensor to display coordinates: orientation, scaling, then calibration.
+    ///
+    /// `caps` must be given if `needs_capabilities`.
+    pub(crate) fn map_point(&self, mut p: Point, caps: Option<&Capabilities>) -> Point {
+        if let Some(caps) = caps {
+            p = self.transform.apply(p, caps.max_x, caps.max_y);
+            if let Some((display_width, display_height)) = self.display_size {
+                let (width, height) = self.transform.size(caps.max_x, caps.max_y);
+                p = Point {
+                    x: transform::scale(p.x, width, display_width),
+                    y: transform::scale(p.y, height, display_height),
+                };
+            }
         }
-        let start_reg = 0x12 + 4 * nth;
-        let mut buf = [0u8; 4];
-        self.i2c.write_read(self.addr, &[start_reg], &mut buf)?;
-
-        if buf[0] >> 7 == 0 {
-            return Ok(None);
-        } else {
-            let x = (u16::from(buf[0] & 0b0111_0000) << 4) | u16::from(buf[1]);
-            let y = (u16::from(buf[0] & 0b0000_1111) << 8) | u16::from(buf[2]);
-            Ok(Some(Point { x, y }))
+        match &self.calibration {
+            Some(calibration) => calibration.apply(p),
+            None => p,
         }
     }
+}
+
+/// No Sitronix controller answered at any of the candidate addresses of `probe_addresses`.
+pub struct NotFound<I2C> {
+    i2c: I2C,
+}
 
-    /// Sensing Counter Registers provide a frame-based scan counter for host to verify current scan rate.
-    pub fn get_sensor_count(&mut self) -> Result<u16, I2C::Error> {
-        let mut buf = [0u8; 2];
+impl<I2C> NotFound<I2C> {
+    /// Recover the bus.
+    pub fn release(self) -> I2C {
         self.i2c
-            .write_read(self.addr, &[regs::SENSING_COUNTER_L], &mut buf)?;
+    }
+}
 
-        Ok(u16::from_be_bytes(buf))
+impl<I2C> core::fmt::Debug for NotFound<I2C> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("NotFound")
     }
+}
 
-    pub fn get_capabilities(&mut self) -> Result<Capabilities, I2C::Error> {
-        let max_contacts = self.read_reg8(regs::CONTACT_COUNT_MAX)?;
-        let misc_info = self.read_reg8(regs::MISC_INFO)?;
+#[cfg(feature = "defmt")]
+impl<I2C> defmt::Format for NotFound<I2C> {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "NotFound")
+    }
+}
 
-        let mut buf = [0u8; 3];
-        self.i2c
-            .write_read(self.addr, &[regs::XY_RESOLUTION_H], &mut buf)?;
+/// Placeholder for an optional pin that is not connected.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct NoPin;
 
-        let x_res = ((u16::from(buf[0]) & 0b0111_0000) << 4) | u16::from(buf[1]);
-        let y_res = ((u16::from(buf[0]) & 0b0000_1111) << 8) | u16::from(buf[2]);
+impl ErrorType for NoPin {
+    type Error = Infallible;
+}
 
-        Ok(Capabilities {
-            max_touches: max_contacts,
-            max_x: x_res,
-            max_y: y_res,
-            smart_wake_up: misc_info & 0b1000_0000 != 0,
-        })
+impl OutputPin for NoPin {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        Ok(())
     }
 
-    fn wait_normal_status(&mut self) -> Result<(), I2C::Error> {
-        let mut status = self.read_reg8(regs::STATUS)?;
-        while status & 0xf0 != 0 {
-            status = self.read_reg8(regs::STATUS)?;
-        }
+    fn set_high(&mut self) -> Result<(), Self::Error> {
         Ok(())
     }
+}
+
+driver::driver!([] []);
 
-    fn read_reg8(&mut self, reg: u8) -> Result<u8, I2C::Error> {
-        let mut buf = [0u8; 1];
-        self.i2c.write_read(self.addr, &[reg], &mut buf)?;
-        Ok(buf[0])
+#[cfg(feature = "eh02")]
+impl<I2C, E> TouchIC<interface::I2c02Interface<I2C>>
+where
+    I2C: embedded_hal_02::blocking::i2c::WriteRead<Error = E>
+        + embedded_hal_02::blocking::i2c::Write<Error = E>,
+{
+    /// Create from an embedded-hal 0.2 I2C bus.
+    pub fn new_eh02(i2c: I2C, addr: u8) -> Self {
+        Self::new_with_interface(interface::I2c02Interface::new(i2c, addr))
     }
 }
 
+#[cfg(feature = "eh02")]
+impl<I2C, INT, RST> TouchIC<interface::I2c02Interface<I2C>, INT, RST> {
+    /// Recover the bus. Attached pins are dropped.
+    pub fn release(self) -> I2C {
+        self.iface.i2c
+    }
+}
+
+impl<IFACE, INT, RST> TouchIC<IFACE, INT, RST>
+where
+    IFACE: Interface,
+    INT: InputPin,
+{
+    /// Check `INTn` without touching the bus.
+    pub fn touch_pending(&mut self) -> Result<bool, Error<IFACE::Error>> {
+        self.int.is_low().map_err(|_| Error::Pin)
+    }
+
+    /// Block until `INTn` is asserted, then read the touch report.
+    ///
+    /// The bus is only accessed once the controller has asserted a report. The report may be
+    /// empty, e.g. the one for a finger lift, so it can be fed to `tracker::Tracker` as is.
+    pub fn wait_for_touch(&mut self) -> Result<TouchReport, Error<IFACE::Error>> {
+        while !self.touch_pending()? {}
+        self.read_report()
+    }
+}
+
+// Register parsing shared by the blocking and async drivers.
+
+/// `buf` starts at `SENSING_COUNTER_L`, so the low byte comes first.
+pub(crate) fn sensor_count_from_raw(buf: [u8; 2]) -> u16 {
+    u16::from_le_bytes(buf)
+}
+
 #[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct Capabilities {
@@ -129,6 +275,219 @@ pub struct Capabilities {
     pub smart_wake_up: bool,
 }
 
+impl Capabilities {
+    /// Whether `point`, in sensor coordinates, lies within the XY resolution.
+    ///
+    /// A zero resolution is treated as unknown and accepts any point.
+    pub(crate) fn contains(&self, point: Point) -> bool {
+        (self.max_x == 0 || point.x < self.max_x) && (self.max_y == 0 || point.y < self.max_y)
+    }
+
+    pub(crate) fn from_raw(max_contacts: u8, misc_info: u8, res: [u8; 3]) -> Self {
+        let x_res = ((u16::from(res[0]) & 0b0111_0000) << 4) | u16::from(res[1]);
+        let y_res = ((u16::from(res[0]) & 0b0000_1111) << 8) | u16::from(res[2]);
+
+        Self {
+            max_touches: max_contacts,
+            max_x: x_res,
+            max_y: y_res,
+            smart_wake_up: misc_info & 0b1000_0000 != 0,
+        }
+    }
+}
+
+/// Time without touch before the controller drops into idle scanning.
+///
+/// Longer timeouts keep first-touch latency low, shorter ones save power.
+///
+/// Stored in the Timeout to Idle Register (`0x03`) in whole seconds, as defined in the
+/// register map of the Sitronix I2C & SPI Interface Protocol A, so the range is 0 to 255 s.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct IdleTimeout(u8);
+
+impl IdleTimeout {
+    pub const MAX: Self = Self(u8::MAX);
+
+    pub const fn from_secs(secs: u8) -> Self {
+        Self(secs)
+    }
+
+    pub const fn as_secs(self) -> u8 {
+        self.0
+    }
+}
+
+impl From<IdleTimeout> for Duration {
+    fn from(timeout: IdleTimeout) -> Self {
+        Duration::from_secs(timeout.0.into())
+    }
+}
+
+/// Fractions of a second are truncated. Fails for durations above `IdleTimeout::MAX`.
+impl TryFrom<Duration> for IdleTimeout {
+    type Error = IdleTimeoutOutOfRange;
+
+    fn try_from(duration: Duration) -> Result<Self, Self::Error> {
+        u8::try_from(duration.as_secs())
+            .map(Self)
+            .map_err(|_| IdleTimeoutOutOfRange)
+    }
+}
+
+/// Duration does not fit the Timeout to Idle Register.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct IdleTimeoutOutOfRange;
+
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DeviceInfo {
+    pub firmware_version: u8,
+    pub firmware_revision: u32,
+    pub chip_id: u8,
+}
+
+/// Status Register
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Status {
+    pub state: DeviceState,
+    pub error: ErrorCode,
+}
+
+impl Status {
+    /// Decode the Status Register: device state in the lower nibble, error code in the upper
+    /// nibble.
+    ///
+    /// Layout as in the Linux st1232 driver, e.g. `STATUS_IDLE` is `0x04` and
+    /// `ERROR_INVALID_ADDRESS` is `0x10`. The error code is deliberately not taken from the
+    /// lower nibble, which would decode the idle state as an error.
+    pub fn from_raw(raw: u8) -> Self {
+        Self {
+            state: DeviceState::from_raw(raw & 0x0f),
+            error: ErrorCode::from_raw(raw >> 4),
+        }
+    }
+
+    pub fn is_normal(&self) -> bool {
+        self.state == DeviceState::Normal
+    }
+
+    /// Whether the controller accepts commands and reports touches: normal, or idle
+    /// scanning which it leaves on the next touch, without an error code. Matches
+    /// `wait_ready` in the Linux st1232 driver, which only accepts `0x00` and `0x04`.
+    pub fn is_ready(&self) -> bool {
+        matches!(self.state, DeviceState::Normal | DeviceState::Idle)
+            && self.error == ErrorCode::NoError
+    }
+}
+
+/// Whether register contents look like a Sitronix controller, used when scanning addresses.
+pub(crate) fn is_plausible_device(status: Status, caps: &Capabilities) -> bool {
+    !matches!(status.state, DeviceState::Unknown(_))
+        && (1..=MAX_CONTACTS).contains(&usize::from(caps.max_touches))
+        && caps.max_x != 0
+        && caps.max_y != 0
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DeviceState {
+    Normal,
+    Init,
+    Error,
+    AutoTune,
+    Idle,
+    PowerDown,
+    Unknown(u8),
+}
+
+impl DeviceState {
+    fn from_raw(raw: u8) -> Self {
+        match raw {
+            0x0 => Self::Normal,
+            0x1 => Self::Init,
+            0x2 => Self::Error,
+            0x3 => Self::AutoTune,
+            0x4 => Self::Idle,
+            0x5 => Self::PowerDown,
+            _ => Self::Unknown(raw),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ErrorCode {
+    NoError,
+    InvalidAddress,
+    InvalidValue,
+    InvalidPlatform,
+    DevNotFound,
+    StackOverflow,
+    InvalidFirmwareParamTable,
+    Unknown(u8),
+}
+
+impl ErrorCode {
+    fn from_raw(raw: u8) -> Self {
+        match raw {
+            0x0 => Self::NoError,
+            0x1 => Self::InvalidAddress,
+            0x2 => Self::InvalidValue,
+            0x3 => Self::InvalidPlatform,
+            0x4 => Self::DevNotFound,
+            0x5 => Self::StackOverflow,
+            0x6 => Self::InvalidFirmwareParamTable,
+            _ => Self::Unknown(raw),
+        }
+    }
+}
+
+/// Device Control Register
+///
+/// Obtained from `get_device_control`, so that bits not covered here are written back unchanged.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DeviceControl {
+    /// Software reset, cleared by the controller once done
+    pub reset: bool,
+    /// Stop scanning until cleared
+    pub power_down: bool,
+    pub proximity_sensing: bool,
+    raw: u8,
+}
+
+impl DeviceControl {
+    const RESET: u8 = 1 << 0;
+    const POWER_DOWN: u8 = 1 << 1;
+    const PROXIMITY_SENSING: u8 = 1 << 7;
+
+    pub(crate) fn from_raw(raw: u8) -> Self {
+        Self {
+            reset: raw & Self::RESET != 0,
+            power_down: raw & Self::POWER_DOWN != 0,
+            proximity_sensing: raw & Self::PROXIMITY_SENSING != 0,
+            raw,
+        }
+    }
+
+    pub(crate) fn to_raw(self) -> u8 {
+        let mut raw = self.raw & !(Self::RESET | Self::POWER_DOWN | Self::PROXIMITY_SENSING);
+        if self.reset {
+            raw |= Self::RESET;
+        }
+        if self.power_down {
+            raw |= Self::POWER_DOWN;
+        }
+        if self.proximity_sensing {
+            raw |= Self::PROXIMITY_SENSING;
+        }
+        raw
+    }
+}
+
 #[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct GestureInfo {
@@ -137,27 +496,40 @@ pub struct GestureInfo {
     pub water: bool,
 }
 
+impl GestureInfo {
+    pub(crate) fn from_raw(raw: u8) -> Self {
+        Self {
+            gesture_type: GestureType::from_raw(raw & 0x0f),
+            proximity: raw & 0b0100_0000 != 0,
+            water: raw & 0b0010_0000 != 0,
+        }
+    }
+}
+
 #[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum GestureType {
-    None = 0,
-    DoubleTab = 1,
-    ZoomIn = 2,
-    ZoomOut = 3,
-    SlideLeftToRight = 4,
-    SlideRightToLeft = 5,
-    SlideTopToBottom = 6,
-    SlideBottomToTop = 7,
-    Palm = 8,
-    SingleTap = 9,
-    LongPress = 10,
-    EndOfLongPress = 11,
-    Drag = 12,
+    None,
+    DoubleTab,
+    ZoomIn,
+    ZoomOut,
+    SlideLeftToRight,
+    SlideRightToLeft,
+    SlideTopToBottom,
+    SlideBottomToTop,
+    Palm,
+    SingleTap,
+    LongPress,
+    EndOfLongPress,
+    Drag,
+    /// Gesture code not defined by the protocol, e.g. from a newer firmware
+    Unknown(u8),
 }
 
 impl GestureType {
-    fn from_u8(raw: u8) -> Self {
-        match raw & 0x0f {
+    /// Decode a gesture code. Codes not defined by the protocol map to `Unknown`.
+    pub fn from_raw(raw: u8) -> Self {
+        match raw {
             0 => Self::None,
             1 => Self::DoubleTab,
             2 => Self::ZoomIn,
@@ -171,14 +543,496 @@ impl GestureType {
             10 => Self::LongPress,
             11 => Self::EndOfLongPress,
             12 => Self::Drag,
-            _ => unreachable!(),
+            _ => Self::Unknown(raw),
+        }
+    }
+
+    /// Encode back to the gesture code, inverse of `from_raw`.
+    pub fn to_raw(self) -> u8 {
+        match self {
+            Self::None => 0,
+            Self::DoubleTab => 1,
+            Self::ZoomIn => 2,
+            Self::ZoomOut => 3,
+            Self::SlideLeftToRight => 4,
+            Self::SlideRightToLeft => 5,
+            Self::SlideTopToBottom => 6,
+            Self::SlideBottomToTop => 7,
+            Self::Palm => 8,
+            Self::SingleTap => 9,
+            Self::LongPress => 10,
+            Self::EndOfLongPress => 11,
+            Self::Drag => 12,
+            Self::Unknown(raw) => raw,
         }
     }
 }
 
+/// Gesture info and contact slots, read in a single burst.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TouchReport {
+    pub gesture: GestureInfo,
+    slots: [Option<Contact>; MAX_CONTACTS],
+    sensing_counter: Option<u16>,
+    is_new: bool,
+}
+
+impl TouchReport {
+    /// Sensing counter of the scan this report was taken from, if the model implements it.
+    pub fn sensing_counter(&self) -> Option<u16> {
+        self.sensing_counter
+    }
+
+    /// `false` if the controller has not completed a scan since the previous report from
+    /// `read_report`, `read_new_report` or `wait_for_touch`, i.e. this report repeats the
+    /// previous frame. Reads through `get_point` or `get_contact` do not count.
+    ///
+    /// Always `true` for models without a sensing counter.
+    pub fn is_new(&self) -> bool {
+        self.is_new
+    }
+
+    /// Report with contacts at `points`, indexed by slot, without a bus.
+    #[cfg(test)]
+    pub(crate) fn from_points(points: &[Option<Point>], is_new: bool) -> Self {
+        let mut slots = [None; MAX_CONTACTS];
+        for (nth, (slot, point)) in slots.iter_mut().zip(points).enumerate() {
+            *slot = point.map(|point| Contact {
+                slot: nth as u8,
+                point,
+                strength: 0,
+            });
+        }
+        Self {
+            gesture: GestureInfo::from_raw(0),
+            slots,
+            sensing_counter: None,
+            is_new,
+        }
+    }
+
+    /// Compare the sensing counter with the one of the previous report.
+    pub(crate) fn check_new(&mut self, last_counter: &mut Option<u16>) {
+        if let Some(counter) = self.sensing_counter {
+            self.is_new = *last_counter != Some(counter);
+            *last_counter = Some(counter);
+        }
+    }
+
+    /// Contact in the nth slot.
+    pub fn contact(&self, nth: u8) -> Option<Contact> {
+        self.slots.get(usize::from(nth)).copied().flatten()
+    }
+
+    /// All valid contacts, in slot order.
+    pub fn contacts(&self) -> impl Iterator<Item = Contact> + '_ {
+        self.slots.iter().flatten().copied()
+    }
+
+    /// Point in the nth contact slot.
+    pub fn point(&self, nth: u8) -> Option<Point> {
+        self.contact(nth).map(|c| c.point)
+    }
+
+    /// All valid contact points, in slot order.
+    pub fn points(&self) -> impl Iterator<Item = Point> + '_ {
+        self.contacts().map(|c| c.point)
+    }
+
+    pub(crate) fn map_points(&mut self, mut f: impl FnMut(Point) -> Point) {
+        for contact in self.slots.iter_mut().flatten() {
+            contact.point = f(contact.point);
+        }
+    }
+
+    /// Number of valid contacts.
+    pub fn len(&self) -> usize {
+        self.contacts().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Contact {
+    /// Contact slot the controller reported this contact in
+    pub slot: u8,
+    pub point: Point,
+    /// Contact strength / area, larger values mean a firmer or wider touch.
+    pub strength: u8,
+}
+
 #[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct Point {
     pub x: u16,
     pub y: u16,
 }
+
+impl Point {
+    pub(crate) fn from_raw(buf: &[u8; 3]) -> Option<Self> {
+        if buf[0] >> 7 == 0 {
+            None
+        } else {
+            let x = (u16::from(buf[0] & 0b0111_0000) << 4) | u16::from(buf[1]);
+            let y = (u16::from(buf[0] & 0b0000_1111) << 8) | u16::from(buf[2]);
+            Some(Point { x, y })
+        }
+    }
+
+    /// Computed in `u64`, as the sum of squares overflows `u32` for full range points.
+    pub(crate) fn distance_sq(self, other: Point) -> u64 {
+        let dx = u64::from(self.x.abs_diff(other.x));
+        let dy = u64::from(self.y.abs_diff(other.y));
+        dx * dx + dy * dy
+    }
+}
+
+/// Round a coordinate computed in `f32`.
+pub(crate) fn to_coord(v: f32) -> u16 {
+    // `as` saturates, covering negative and out of range values
+    (v + 0.5) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Copy, Clone, PartialEq, Debug)]
+    struct Nack;
+
+    /// Register file of a bus-free controller.
+    struct FakeBus {
+        regs: [u8; 256],
+        /// Values of successive `STATUS` reads, the last one repeats
+        status: &'static [u8],
+        status_reads: usize,
+        /// Number of reads to fail before answering
+        nacks: u32,
+    }
+
+    impl FakeBus {
+        /// Ready ST1633i with an 800 x 480 resolution and 5 contacts.
+        fn new() -> Self {
+            let mut regs = [0; 256];
+            regs[usize::from(regs::CONTACT_COUNT_MAX)] = 5;
+            regs[usize::from(regs::XY_RESOLUTION_H)] = 0x31;
+            regs[usize::from(regs::X_RESOLUTION_L)] = 0x20;
+            regs[usize::from(regs::Y_RESOLUTION_L)] = 0xE0;
+            regs[usize::from(regs::CHIP_ID)] = 0x0A;
+            Self {
+                regs,
+                status: &[],
+                status_reads: 0,
+                nacks: 0,
+            }
+        }
+
+        fn with_status(mut self, status: &'static [u8]) -> Self {
+            self.status = status;
+            self
+        }
+
+        fn set_counter(&mut self, counter: u16) {
+            let [low, high] = counter.to_le_bytes();
+            self.regs[usize::from(regs::SENSING_COUNTER_L)] = low;
+            self.regs[usize::from(regs::SENSING_COUNTER_H)] = high;
+        }
+
+        /// Fill the nth 4-byte contact slot.
+        fn set_contact(&mut self, nth: usize, point: Point, strength: u8) {
+            let [x_high, x_low] = point.x.to_be_bytes();
+            let [y_high, y_low] = point.y.to_be_bytes();
+            let start = usize::from(regs::XY_COORDINATES) + 4 * nth;
+            self.regs[start..start + 4].copy_from_slice(&[
+                0x80 | x_high << 4 | y_high,
+                x_low,
+                y_low,
+                strength,
+            ]);
+        }
+    }
+
+    impl Interface for FakeBus {
+        type Error = Nack;
+
+        fn read_regs(&mut self, reg: u8, buf: &mut [u8]) -> Result<(), Self::Error> {
+            if self.nacks > 0 {
+                self.nacks -= 1;
+                return Err(Nack);
+            }
+            if reg == regs::STATUS && !self.status.is_empty() {
+                let i = self.status_reads.min(self.status.len() - 1);
+                self.regs[usize::from(reg)] = self.status[i];
+                self.status_reads += 1;
+            }
+            let start = usize::from(reg);
+            buf.copy_from_slice(&self.regs[start..start + buf.len()]);
+            Ok(())
+        }
+
+        fn write_reg8(&mut self, reg: u8, value: u8) -> Result<(), Self::Error> {
+            self.regs[usize::from(reg)] = value;
+            Ok(())
+        }
+    }
+
+    /// Delay which only adds up the requested time.
+    #[derive(Default)]
+    struct CountingDelay {
+        ns: u64,
+    }
+
+    impl CountingDelay {
+        fn ms(&self) -> u64 {
+            self.ns / 1_000_000
+        }
+    }
+
+    impl DelayNs for CountingDelay {
+        fn delay_ns(&mut self, ns: u32) {
+            self.ns += u64::from(ns);
+        }
+    }
+
+    #[test]
+    fn status_layout() {
+        let status = |state, error| Status { state, error };
+        assert_eq!(
+            Status::from_raw(0x00),
+            status(DeviceState::Normal, ErrorCode::NoError)
+        );
+        assert_eq!(
+            Status::from_raw(0x04),
+            status(DeviceState::Idle, ErrorCode::NoError)
+        );
+        assert_eq!(
+            Status::from_raw(0x02),
+            status(DeviceState::Error, ErrorCode::NoError)
+        );
+        assert_eq!(
+            Status::from_raw(0x10),
+            status(DeviceState::Normal, ErrorCode::InvalidAddress)
+        );
+        assert_eq!(
+            Status::from_raw(0x62),
+            status(DeviceState::Error, ErrorCode::InvalidFirmwareParamTable)
+        );
+    }
+
+    #[test]
+    fn status_unknown_values() {
+        assert_eq!(Status::from_raw(0x06).state, DeviceState::Unknown(0x06));
+        assert_eq!(Status::from_raw(0x0F).state, DeviceState::Unknown(0x0F));
+        assert_eq!(Status::from_raw(0x70).error, ErrorCode::Unknown(0x07));
+        assert_eq!(Status::from_raw(0xF0).error, ErrorCode::Unknown(0x0F));
+    }
+
+    #[test]
+    fn status_ready() {
+        assert!(Status::from_raw(0x00).is_ready());
+        assert!(Status::from_raw(0x04).is_ready());
+        for raw in [0x01, 0x02, 0x03, 0x05, 0x10, 0x14, 0xF0] {
+            assert!(!Status::from_raw(raw).is_ready(), "{raw:#04x}");
+        }
+    }
+
+    fn touch_ic(bus: FakeBus) -> TouchIC<FakeBus> {
+        TouchIC::new_with_interface(bus).with_init_timeout(5)
+    }
+
+    #[test]
+    fn init_waits_for_ready() {
+        let mut ic = touch_ic(FakeBus::new().with_status(&[0x01, 0x01, 0x04]));
+        let mut delay = CountingDelay::default();
+        assert_eq!(ic.init(&mut delay), Ok(()));
+        assert_eq!(ic.iface.status_reads, 3);
+        assert_eq!(delay.ms(), 2);
+        assert_eq!(ic.caps.map(|c| (c.max_x, c.max_y)), Some((800, 480)));
+    }
+
+    #[test]
+    fn init_times_out_with_last_status() {
+        let mut ic = touch_ic(FakeBus::new().with_status(&[0x01]));
+        let mut delay = CountingDelay::default();
+        assert_eq!(ic.init(&mut delay), Err(Error::Timeout { status: 0x01 }));
+        assert_eq!(delay.ms(), 5);
+    }
+
+    #[test]
+    fn init_is_not_ready_with_error_code() {
+        // normal state, but `ERROR_INVALID_ADDRESS`
+        let mut ic = touch_ic(FakeBus::new().with_status(&[0x10]));
+        let mut delay = CountingDelay::default();
+        assert_eq!(ic.init(&mut delay), Err(Error::Timeout { status: 0x10 }));
+    }
+
+    #[test]
+    fn init_fails_on_error_state() {
+        let mut ic = touch_ic(FakeBus::new().with_status(&[0x01, 0x02, 0x00]));
+        let mut delay = CountingDelay::default();
+        assert_eq!(ic.init(&mut delay), Err(Error::BadStatus { status: 0x02 }));
+        assert_eq!(ic.iface.status_reads, 2);
+    }
+
+    #[test]
+    fn init_retries_bus_errors() {
+        let mut bus = FakeBus::new().with_status(&[0x00]);
+        bus.nacks = 3;
+        let mut ic = touch_ic(bus);
+        let mut delay = CountingDelay::default();
+        assert_eq!(ic.init(&mut delay), Ok(()));
+        assert_eq!(delay.ms(), 3);
+    }
+
+    #[test]
+    fn init_returns_bus_error_on_timeout() {
+        let mut bus = FakeBus::new().with_status(&[0x00]);
+        bus.nacks = u32::MAX;
+        let mut ic = touch_ic(bus);
+        let mut delay = CountingDelay::default();
+        assert_eq!(ic.init(&mut delay), Err(Error::Bus(Nack)));
+        assert_eq!(delay.ms(), 5);
+    }
+
+    #[test]
+    fn soft_reset_waits_for_ready() {
+        let mut ic = touch_ic(FakeBus::new().with_status(&[0x01, 0x00]));
+        let mut delay = CountingDelay::default();
+        assert_eq!(ic.soft_reset(&mut delay), Ok(()));
+        assert_eq!(ic.iface.status_reads, 2);
+    }
+
+    #[test]
+    fn check_new_compares_counters() {
+        let mut last_counter = None;
+        let mut report = TouchReport::from_points(&[], true);
+        report.sensing_counter = Some(7);
+        report.check_new(&mut last_counter);
+        assert!(report.is_new());
+        assert_eq!(last_counter, Some(7));
+
+        report.check_new(&mut last_counter);
+        assert!(!report.is_new());
+
+        report.sensing_counter = Some(8);
+        report.check_new(&mut last_counter);
+        assert!(report.is_new());
+
+        // models without a counter report every frame as new
+        let mut report = TouchReport::from_points(&[], true);
+        report.check_new(&mut last_counter);
+        assert!(report.is_new());
+        assert_eq!(last_counter, Some(8));
+    }
+
+    #[test]
+    fn read_new_report_once_per_scan() {
+        let mut bus = FakeBus::new();
+        bus.set_counter(1);
+        bus.set_contact(0, Point { x: 10, y: 20 }, 0x33);
+        let mut ic = touch_ic(bus);
+
+        let report = ic.read_new_report().unwrap().unwrap();
+        assert_eq!(report.point(0), Some(Point { x: 10, y: 20 }));
+        assert_eq!(ic.read_new_report(), Ok(None));
+        assert!(!ic.read_report().unwrap().is_new());
+
+        ic.iface.set_counter(2);
+        assert!(ic.read_new_report().unwrap().is_some());
+        assert_eq!(ic.read_new_report(), Ok(None));
+    }
+
+    #[test]
+    fn slot_getters_leave_counter_alone() {
+        let mut bus = FakeBus::new();
+        bus.set_counter(1);
+        bus.set_contact(1, Point { x: 10, y: 20 }, 0x33);
+        let mut ic = touch_ic(bus);
+
+        assert_eq!(ic.get_point(0), Ok(None));
+        assert_eq!(ic.get_point1(), Ok(Some(Point { x: 10, y: 20 })));
+        assert_eq!(
+            ic.get_contact(1),
+            Ok(Some(Contact {
+                slot: 1,
+                point: Point { x: 10, y: 20 },
+                strength: 0x33,
+            }))
+        );
+        assert!(ic.read_new_report().unwrap().is_some());
+    }
+
+    #[test]
+    fn invalid_report_does_not_advance_counter() {
+        let mut bus = FakeBus::new();
+        bus.set_counter(1);
+        bus.set_contact(0, Point { x: 800, y: 20 }, 0);
+        let mut ic = touch_ic(bus);
+        ic.init(&mut CountingDelay::default()).unwrap();
+
+        assert_eq!(ic.read_new_report(), Err(Error::InvalidCoordinates));
+        assert_eq!(ic.get_point(0), Err(Error::InvalidCoordinates));
+
+        // same scan, read again after the glitch
+        ic.iface.set_contact(0, Point { x: 799, y: 20 }, 0);
+        let report = ic.read_new_report().unwrap().unwrap();
+        assert_eq!(report.point(0), Some(Point { x: 799, y: 20 }));
+    }
+
+    #[test]
+    fn probe_selects_model() {
+        let mut ic = touch_ic(FakeBus::new()).with_model(ChipModel::St1232);
+        assert_eq!(ic.probe(), Ok(ChipModel::St1633i));
+        assert_eq!(ic.model(), ChipModel::St1633i);
+
+        ic.iface.regs[usize::from(regs::CHIP_ID)] = 0x06;
+        assert_eq!(ic.probe(), Ok(ChipModel::St1633));
+        assert_eq!(ic.model(), ChipModel::St1633);
+    }
+
+    #[test]
+    fn probe_rejects_unknown_chip() {
+        let mut bus = FakeBus::new();
+        bus.regs[usize::from(regs::CHIP_ID)] = 0x42;
+        let mut ic = touch_ic(bus).with_model(ChipModel::St1912);
+        assert_eq!(ic.probe(), Err(Error::UnsupportedChip));
+        assert_eq!(ic.model(), ChipModel::St1912);
+
+        ic.iface.nacks = 1;
+        assert_eq!(ic.probe(), Err(Error::Bus(Nack)));
+    }
+
+    #[test]
+    fn device_control_keeps_reserved_bits() {
+        let mut ctrl = DeviceControl::from_raw(0x7C);
+        assert!(!ctrl.reset && !ctrl.power_down && !ctrl.proximity_sensing);
+        assert_eq!(ctrl.to_raw(), 0x7C);
+
+        ctrl.power_down = true;
+        assert_eq!(ctrl.to_raw(), 0x7E);
+        ctrl.power_down = false;
+        ctrl.proximity_sensing = true;
+        assert_eq!(ctrl.to_raw(), 0xFC);
+
+        let ctrl = DeviceControl::from_raw(0xFF);
+        assert!(ctrl.reset && ctrl.power_down && ctrl.proximity_sensing);
+        assert_eq!(ctrl.to_raw(), 0xFF);
+    }
+
+    #[test]
+    fn power_down_read_modify_write() {
+        let mut bus = FakeBus::new();
+        bus.regs[usize::from(regs::DEVICE_CONTROL)] = 0x7C;
+        let mut ic = touch_ic(bus);
+
+        ic.power_down().unwrap();
+        assert_eq!(ic.iface.regs[usize::from(regs::DEVICE_CONTROL)], 0x7E);
+        ic.wake_up().unwrap();
+        assert_eq!(ic.iface.regs[usize::from(regs::DEVICE_CONTROL)], 0x7C);
+    }
+}
@@ -0,0 +1,277 @@
+use crate::{regs, sensor_count_from_raw, Contact, GestureInfo, Point, TouchReport, MAX_CONTACTS};
+
+/// Offset of the first contact slot after `ADVANCED_TOUCH_INFO`.
+const CONTACTS_OFFSET: usize = (regs::XY_COORDINATES - regs::ADVANCED_TOUCH_INFO) as usize;
+/// Sensing counter through the register before `ADVANCED_TOUCH_INFO`, read in the same burst.
+pub(crate) const COUNTER_PREFIX_LEN: usize =
+    (regs::ADVANCED_TOUCH_INFO - regs::SENSING_COUNTER_L) as usize;
+
+/// Supported controller families.
+///
+/// Selects the touch report layout and which optional registers are available.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ChipModel {
+    /// ST1232 and the older ST1x32 parts, 3 bytes per contact with strength stored after the slots
+    St1232,
+    St1615,
+    St1624,
+    St1633,
+    #[default]
+    St1633i,
+    St1727,
+    St1912,
+}
+
+impl ChipModel {
+    const ALL: [ChipModel; 7] = [
+        ChipModel::St1232,
+        ChipModel::St1615,
+        ChipModel::St1624,
+        ChipModel::St1633,
+        ChipModel::St1633i,
+        ChipModel::St1727,
+        ChipModel::St1912,
+    ];
+
+    /// Value of the `CHIP_ID` register, `None` if the model does not implement it.
+    ///
+    /// Values as given for `CHIP_ID` (`0xF4`) by the Sitronix Touch IC Touch Screen
+    /// Controller I2C & SPI Interface Protocol A, see Ref in the README.
+    pub const fn chip_id(self) -> Option<u8> {
+        match self {
+            ChipModel::St1232 => None,
+            ChipModel::St1615 => Some(0x0C),
+            ChipModel::St1624 => Some(0x0D),
+            ChipModel::St1633 => Some(0x06),
+            ChipModel::St1633i => Some(0x0A),
+            ChipModel::St1727 => Some(0x0E),
+            ChipModel::St1912 => Some(0x0F),
+        }
+    }
+
+    /// Resolve the model from the `CHIP_ID` register.
+    pub fn from_chip_id(chip_id: u8) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|model| model.chip_id() == Some(chip_id))
+    }
+
+    /// Number of contact slots in a touch report.
+    pub const fn max_contacts(self) -> usize {
+        match self {
+            ChipModel::St1232 => 2,
+            ChipModel::St1615 | ChipModel::St1624 | ChipModel::St1633 => 5,
+            ChipModel::St1633i | ChipModel::St1727 | ChipModel::St1912 => MAX_CONTACTS,
+        }
+    }
+
+    /// Bytes per contact slot.
+    pub const fn contact_stride(self) -> usize {
+        match self {
+            ChipModel::St1232 => 3,
+            _ => 4,
+        }
+    }
+
+    /// Whether `ADVANCED_TOUCH_INFO` carries gesture, proximity and water flags.
+    pub const fn has_gestures(self) -> bool {
+        !matches!(self, ChipModel::St1232 | ChipModel::St1615)
+    }
+
+    /// Whether `FIRMWARE_REVISION` and `CHIP_ID` are implemented.
+    pub const fn has_device_info(self) -> bool {
+        self.chip_id().is_some()
+    }
+
+    /// Whether the sensing counter is implemented, used to detect repeated frames.
+    pub const fn has_sensing_counter(self) -> bool {
+        !matches!(self, ChipModel::St1232)
+    }
+
+    /// First register of a touch report burst.
+    pub(crate) const fn report_start(self) -> u8 {
+        if self.has_sensing_counter() {
+            regs::SENSING_COUNTER_L
+        } else {
+            regs::ADVANCED_TOUCH_INFO
+        }
+    }
+
+    /// Length of a touch report burst, starting at `report_start`.
+    pub(crate) const fn report_len(self) -> usize {
+        let prefix = if self.has_sensing_counter() {
+            COUNTER_PREFIX_LEN
+        } else {
+            0
+        };
+        let slots = CONTACTS_OFFSET + self.contact_stride() * self.max_contacts();
+        match self {
+            // one strength byte per slot
+            ChipModel::St1232 => prefix + slots + self.max_contacts(),
+            _ => prefix + slots,
+        }
+    }
+
+    pub(crate) fn parse_report(self, buf: &[u8]) -> TouchReport {
+        let (sensing_counter, buf) = if self.has_sensing_counter() {
+            let (prefix, buf) = buf.split_at(COUNTER_PREFIX_LEN);
+            (Some(sensor_count_from_raw([prefix[0], prefix[1]])), buf)
+        } else {
+            (None, buf)
+        };
+
+        let gesture = if self.has_gestures() {
+            GestureInfo::from_raw(buf[0])
+        } else {
+            GestureInfo::from_raw(0)
+        };
+
+        let mut slots = [None; MAX_CONTACTS];
+        for (nth, slot) in slots.iter_mut().enumerate().take(self.max_contacts()) {
+            let start = CONTACTS_OFFSET + nth * self.contact_stride();
+            let strength = match self {
+                ChipModel::St1232 => {
+                    buf[CONTACTS_OFFSET + self.contact_stride() * self.max_contacts() + nth]
+                }
+                _ => buf[start + 3],
+            };
+            *slot =
+                Point::from_raw(buf[start..start + 3].try_into().unwrap()).map(|point| Contact {
+                    slot: nth as u8,
+                    point,
+                    strength,
+                });
+        }
+        TouchReport {
+            gesture,
+            slots,
+            sensing_counter,
+            is_new: true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GestureType, REPORT_LEN};
+
+    /// Raw coordinate bytes of a valid contact.
+    fn raw_point(x: u16, y: u16) -> [u8; 3] {
+        [
+            0x80 | ((x >> 4) as u8 & 0x70) | (y >> 8) as u8,
+            x as u8,
+            y as u8,
+        ]
+    }
+
+    #[test]
+    fn report_lengths() {
+        for (model, len) in [
+            (ChipModel::St1232, 2 + 2 * 3 + 2),
+            (ChipModel::St1615, 9 + 2 + 5 * 4),
+            (ChipModel::St1624, 9 + 2 + 5 * 4),
+            (ChipModel::St1633, 9 + 2 + 5 * 4),
+            (ChipModel::St1633i, 9 + 2 + 10 * 4),
+            (ChipModel::St1727, 9 + 2 + 10 * 4),
+            (ChipModel::St1912, 9 + 2 + 10 * 4),
+        ] {
+            assert_eq!(model.report_len(), len, "{model:?}");
+            assert!(model.report_len() <= REPORT_LEN);
+        }
+    }
+
+    #[test]
+    fn st1232_layout() {
+        let model = ChipModel::St1232;
+        assert_eq!(model.report_start(), regs::ADVANCED_TOUCH_INFO);
+
+        // gesture byte, keys, 2 slots of 3 bytes, then 2 strength bytes
+        let mut buf = [0u8; 10];
+        buf[0] = 0x02;
+        buf[2..5].copy_from_slice(&raw_point(0x123, 0x456));
+        buf[8] = 0x11;
+        buf[9] = 0x22;
+
+        let report = model.parse_report(&buf);
+        assert_eq!(report.sensing_counter(), None);
+        assert!(report.is_new());
+        assert_eq!(report.gesture.gesture_type, GestureType::None);
+        assert_eq!(
+            report.contact(0),
+            Some(Contact {
+                slot: 0,
+                point: Point { x: 0x123, y: 0x456 },
+                strength: 0x11,
+            })
+        );
+        assert_eq!(report.contact(1), None);
+        assert_eq!(report.len(), 1);
+    }
+
+    #[test]
+    fn four_byte_layouts() {
+        for model in [ChipModel::St1633, ChipModel::St1912] {
+            let slots = model.max_contacts();
+            assert_eq!(model.report_start(), regs::SENSING_COUNTER_L);
+
+            let mut buf = [0u8; REPORT_LEN];
+            let buf = &mut buf[..model.report_len()];
+            // sensing counter, low byte first
+            buf[0] = 0x34;
+            buf[1] = 0x12;
+            let body = &mut buf[COUNTER_PREFIX_LEN..];
+            body[0] = 0x42;
+            let last = CONTACTS_OFFSET + 4 * (slots - 1);
+            body[CONTACTS_OFFSET..CONTACTS_OFFSET + 3].copy_from_slice(&raw_point(1, 2));
+            body[CONTACTS_OFFSET + 3] = 0x55;
+            body[last..last + 3].copy_from_slice(&raw_point(0x7FF, 0xFFF));
+            body[last + 3] = 0x66;
+
+            let report = model.parse_report(buf);
+            assert_eq!(report.sensing_counter(), Some(0x1234), "{model:?}");
+            assert_eq!(report.gesture.gesture_type, GestureType::ZoomIn);
+            assert!(report.gesture.proximity);
+            assert_eq!(
+                report.contact(0),
+                Some(Contact {
+                    slot: 0,
+                    point: Point { x: 1, y: 2 },
+                    strength: 0x55,
+                })
+            );
+            assert_eq!(
+                report.contact(slots as u8 - 1),
+                Some(Contact {
+                    slot: slots as u8 - 1,
+                    point: Point { x: 0x7FF, y: 0xFFF },
+                    strength: 0x66,
+                })
+            );
+            assert_eq!(report.len(), 2);
+            assert_eq!(report.contact(slots as u8), None);
+        }
+    }
+
+    #[test]
+    fn gesture_byte_ignored_without_gestures() {
+        let mut buf = [0u8; REPORT_LEN];
+        buf[COUNTER_PREFIX_LEN] = 0x42;
+        let report = ChipModel::St1615.parse_report(&buf[..ChipModel::St1615.report_len()]);
+        assert_eq!(report.gesture.gesture_type, GestureType::None);
+        assert!(!report.gesture.proximity);
+    }
+
+    #[test]
+    fn chip_id_round_trip() {
+        for model in ChipModel::ALL {
+            match model.chip_id() {
+                Some(id) => assert_eq!(ChipModel::from_chip_id(id), Some(model)),
+                None => assert_eq!(model, ChipModel::St1232),
+            }
+        }
+        assert_eq!(ChipModel::from_chip_id(0x00), None);
+        assert_eq!(ChipModel::from_chip_id(0xFF), None);
+    }
+}
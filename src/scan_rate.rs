@@ -0,0 +1,144 @@
+//! Scan rate measurement from the sensing counter.
+
+/// Monotonic host clock in microseconds, allowed to wrap around.
+pub trait Clock {
+    fn now_us(&mut self) -> u32;
+}
+
+impl<F> Clock for F
+where
+    F: FnMut() -> u32,
+{
+    fn now_us(&mut self) -> u32 {
+        self()
+    }
+}
+
+/// Measures the controller scan rate by sampling the sensing counter against a host clock.
+///
+/// Both the 16-bit sensing counter and the clock may wrap between samples, as long as
+/// less than one full period of either passes between two calls to `sample`.
+pub struct ScanRateMeter<C> {
+    clock: C,
+    last: Option<(u16, u32)>,
+    frames: u64,
+    elapsed_us: u64,
+}
+
+impl<C> ScanRateMeter<C>
+where
+    C: Clock,
+{
+    pub fn new(clock: C) -> Self {
+        Self {
+            clock,
+            last: None,
+            frames: 0,
+            elapsed_us: 0,
+        }
+    }
+
+    /// Feed a value from `get_sensor_count` or `TouchReport::sensing_counter`.
+    pub fn sample(&mut self, counter: u16) {
+        let now = self.clock.now_us();
+        if let Some((last_counter, last_time)) = self.last {
+            self.frames += u64::from(counter.wrapping_sub(last_counter));
+            self.elapsed_us += u64::from(now.wrapping_sub(last_time));
+        }
+        self.last = Some((counter, now));
+    }
+
+    /// Scans counted since the first sample.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Host time since the first sample.
+    pub fn elapsed_us(&self) -> u64 {
+        self.elapsed_us
+    }
+
+    /// Average scan rate since the first sample, `None` until two samples with time in
+    /// between have been taken.
+    pub fn frames_per_second(&self) -> Option<f32> {
+        if self.elapsed_us == 0 {
+            return None;
+        }
+        Some(self.frames as f32 * 1_000_000.0 / self.elapsed_us as f32)
+    }
+
+    /// Start a new measurement.
+    pub fn reset(&mut self) {
+        self.last = None;
+        self.frames = 0;
+        self.elapsed_us = 0;
+    }
+
+    pub fn release(self) -> C {
+        self.clock
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Meter whose clock returns `times` in order.
+    fn meter<const N: usize>(times: [u32; N]) -> ScanRateMeter<impl Clock> {
+        let mut times = times.into_iter();
+        ScanRateMeter::new(move || times.next().unwrap())
+    }
+
+    #[test]
+    fn frames_per_second() {
+        let mut m = meter([1_000, 501_000, 1_001_000]);
+        m.sample(100);
+        m.sample(130);
+        m.sample(160);
+        assert_eq!(m.frames(), 60);
+        assert_eq!(m.elapsed_us(), 1_000_000);
+        assert_eq!(m.frames_per_second(), Some(60.0));
+    }
+
+    #[test]
+    fn none_before_second_sample() {
+        let mut m = meter([0, 10_000]);
+        assert_eq!(m.frames_per_second(), None);
+        m.sample(7);
+        assert_eq!(m.frames_per_second(), None);
+        m.sample(8);
+        assert_eq!(m.frames_per_second(), Some(100.0));
+    }
+
+    #[test]
+    fn counter_wraps() {
+        let mut m = meter([0, 100_000]);
+        m.sample(0xFFF0);
+        m.sample(0x0010);
+        assert_eq!(m.frames(), 0x20);
+    }
+
+    #[test]
+    fn clock_wraps() {
+        let mut m = meter([u32::MAX - 49_999, 50_000]);
+        m.sample(0);
+        m.sample(10);
+        assert_eq!(m.elapsed_us(), 100_000);
+        assert_eq!(m.frames_per_second(), Some(100.0));
+    }
+
+    #[test]
+    fn reset_starts_over() {
+        let mut m = meter([0, 100_000, 200_000, 300_000]);
+        m.sample(0);
+        m.sample(10);
+        m.reset();
+        assert_eq!(m.frames(), 0);
+        assert_eq!(m.frames_per_second(), None);
+        // the first sample after a reset only sets the starting point
+        m.sample(500);
+        assert_eq!(m.frames_per_second(), None);
+        m.sample(520);
+        assert_eq!(m.frames_per_second(), Some(200.0));
+    }
+}
@@ -0,0 +1,422 @@
+//! Multi-touch tracking across touch reports.
+//!
+//! Turns per-frame contact slots into `TouchEvent`s with finger IDs that stay stable for
+//! as long as the finger stays down. Points can optionally be smoothed per finger, see
+//! `Tracker::with_filter`.
+
+use crate::filter::{Filter, FilterConfig};
+use crate::{Point, TouchReport, MAX_CONTACTS};
+
+/// Default jump, in report coordinates, above which a slot is assumed to have been reused
+/// by a new finger between two polls.
+pub const DEFAULT_MAX_JUMP: u16 = 200;
+
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TouchEvent {
+    Down {
+        id: u8,
+        point: Point,
+    },
+    Move {
+        id: u8,
+        point: Point,
+    },
+    /// Finger lifted, with its last known point
+    Up {
+        id: u8,
+        point: Point,
+    },
+}
+
+impl TouchEvent {
+    pub fn id(&self) -> u8 {
+        match *self {
+            TouchEvent::Down { id, .. }
+            | TouchEvent::Move { id, .. }
+            | TouchEvent::Up { id, .. } => id,
+        }
+    }
+
+    pub fn point(&self) -> Point {
+        match *self {
+            TouchEvent::Down { point, .. }
+            | TouchEvent::Move { point, .. }
+            | TouchEvent::Up { point, .. } => point,
+        }
+    }
+}
+
+/// A finger currently down.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Finger {
+    pub id: u8,
+    pub point: Point,
+}
+
+/// Events produced by one `Tracker::update`, ups first, then moves, then downs.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Events {
+    events: [Option<TouchEvent>; 2 * MAX_CONTACTS],
+    len: usize,
+    pos: usize,
+}
+
+impl Events {
+    fn new() -> Self {
+        Self {
+            events: [None; 2 * MAX_CONTACTS],
+            len: 0,
+            pos: 0,
+        }
+    }
+
+    fn push(&mut self, event: TouchEvent) {
+        self.events[self.len] = Some(event);
+        self.len += 1;
+    }
+}
+
+impl Iterator for Events {
+    type Item = TouchEvent;
+
+    fn next(&mut self) -> Option<TouchEvent> {
+        if self.pos == self.len {
+            return None;
+        }
+        self.pos += 1;
+        self.events[self.pos - 1]
+    }
+}
+
+/// Keeps per-finger state across frames, keyed by contact slot.
+///
+/// A slot that disappears produces `Up`. A slot whose point jumps further than the
+/// configured maximum is treated as reused by a new finger: `Up` for the old finger, then
+/// `Down` with a fresh ID.
+///
+/// Jumps are detected on raw report points, events and `fingers` carry filtered points.
+#[derive(Clone, Debug)]
+pub struct Tracker {
+    slots: [Option<Finger>; MAX_CONTACTS],
+    raw: [Point; MAX_CONTACTS],
+    filters: [Filter; MAX_CONTACTS],
+    next_id: u8,
+    max_jump: u16,
+}
+
+impl Default for Tracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Tracker {
+    pub fn new() -> Self {
+        Self {
+            slots: [None; MAX_CONTACTS],
+            raw: [Point { x: 0, y: 0 }; MAX_CONTACTS],
+            filters: [Filter::new(FilterConfig::default()); MAX_CONTACTS],
+            next_id: 0,
+            max_jump: DEFAULT_MAX_JUMP,
+        }
+    }
+
+    /// Jump in report coordinates above which a slot is treated as reused.
+    pub fn with_max_jump(mut self, max_jump: u16) -> Self {
+        self.max_jump = max_jump;
+        self
+    }
+
+    /// Smooth finger points with `config`.
+    pub fn with_filter(mut self, config: FilterConfig) -> Self {
+        self.set_filter(config);
+        self
+    }
+
+    pub fn filter_config(&self) -> &FilterConfig {
+        self.filters[0].config()
+    }
+
+    /// Change filter parameters. Fingers already down keep their filter history.
+    pub fn set_filter(&mut self, config: FilterConfig) {
+        for filter in self.filters.iter_mut() {
+            filter.set_config(config);
+        }
+    }
+
+    /// Turn filtering on or off, keeping the other parameters.
+    pub fn set_filter_enabled(&mut self, enabled: bool) {
+        self.set_filter(FilterConfig {
+            enabled,
+            ..*self.filter_config()
+        });
+    }
+
+    /// Fingers currently down.
+    pub fn fingers(&self) -> impl Iterator<Item = Finger> + '_ {
+        self.slots.iter().flatten().copied()
+    }
+
+    /// Feed the next report and get the resulting events.
+    ///
+    /// Reports repeating the previous frame (see `TouchReport::is_new`) are ignored.
+    pub fn update(&mut self, report: &TouchReport) -> Events {
+        let mut ups = Events::new();
+        if !report.is_new() {
+            return ups;
+        }
+        let mut moves = Events::new();
+        let mut downs = Events::new();
+
+        for nth in 0..MAX_CONTACTS {
+            let current = report.point(nth as u8);
+            match (self.slots[nth], current) {
+                (Some(finger), Some(raw))
+                    if self.raw[nth].distance_sq(raw) <= self.max_jump_sq() =>
+                {
+                    self.raw[nth] = raw;
+                    let point = self.filters[nth].apply(raw);
+                    if finger.point != point {
+                        moves.push(TouchEvent::Move {
+                            id: finger.id,
+                            point,
+                        });
+                        self.slots[nth] = Some(Finger { point, ..finger });
+                    }
+                }
+                (previous, current) => {
+                    if let Some(finger) = previous {
+                        ups.push(TouchEvent::Up {
+                            id: finger.id,
+                            point: finger.point,
+                        });
+                        self.slots[nth] = None;
+                    }
+                    if let Some(raw) = current {
+                        self.raw[nth] = raw;
+                        self.filters[nth].reset();
+                        let point = self.filters[nth].apply(raw);
+                        let id = self.alloc_id();
+                        downs.push(TouchEvent::Down { id, point });
+                        self.slots[nth] = Some(Finger { id, point });
+                    }
+                }
+            }
+        }
+
+        for event in moves.chain(downs) {
+            ups.push(event);
+        }
+        ups
+    }
+
+    /// Release all fingers, e.g. when polling stops.
+    pub fn reset(&mut self) -> Events {
+        let mut ups = Events::new();
+        for slot in self.slots.iter_mut() {
+            if let Some(finger) = slot.take() {
+                ups.push(TouchEvent::Up {
+                    id: finger.id,
+                    point: finger.point,
+                });
+            }
+        }
+        ups
+    }
+
+    fn max_jump_sq(&self) -> u64 {
+        u64::from(self.max_jump) * u64::from(self.max_jump)
+    }
+
+    /// Next ID not held by a finger that is still down.
+    fn alloc_id(&mut self) -> u8 {
+        loop {
+            let id = self.next_id;
+            self.next_id = self.next_id.wrapping_add(1);
+            if self.fingers().all(|finger| finger.id != id) {
+                return id;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn p(x: u16, y: u16) -> Point {
+        Point { x, y }
+    }
+
+    fn report(points: &[Option<Point>]) -> TouchReport {
+        TouchReport::from_points(points, true)
+    }
+
+    fn events<const N: usize>(mut events: Events) -> [Option<TouchEvent>; N] {
+        let mut out = [None; N];
+        for slot in out.iter_mut() {
+            *slot = events.next();
+        }
+        assert_eq!(events.next(), None, "more than {N} events");
+        out
+    }
+
+    #[test]
+    fn down_move_up() {
+        let mut tracker = Tracker::new();
+
+        let e = events::<1>(tracker.update(&report(&[Some(p(10, 20))])));
+        assert_eq!(
+            e,
+            [Some(TouchEvent::Down {
+                id: 0,
+                point: p(10, 20)
+            })]
+        );
+
+        let e = events::<1>(tracker.update(&report(&[Some(p(15, 25))])));
+        assert_eq!(
+            e,
+            [Some(TouchEvent::Move {
+                id: 0,
+                point: p(15, 25)
+            })]
+        );
+
+        // unchanged point, no event
+        let e = events::<1>(tracker.update(&report(&[Some(p(15, 25))])));
+        assert_eq!(e, [None]);
+        assert_eq!(tracker.fingers().count(), 1);
+
+        let e = events::<1>(tracker.update(&report(&[])));
+        assert_eq!(
+            e,
+            [Some(TouchEvent::Up {
+                id: 0,
+                point: p(15, 25)
+            })]
+        );
+        assert_eq!(tracker.fingers().count(), 0);
+    }
+
+    #[test]
+    fn events_are_ordered_ups_moves_downs() {
+        let mut tracker = Tracker::new();
+        tracker.update(&report(&[Some(p(0, 0)), Some(p(500, 500))]));
+
+        let e = events::<3>(tracker.update(&report(&[None, Some(p(510, 500)), Some(p(900, 0))])));
+        assert_eq!(
+            e,
+            [
+                Some(TouchEvent::Up {
+                    id: 0,
+                    point: p(0, 0)
+                }),
+                Some(TouchEvent::Move {
+                    id: 1,
+                    point: p(510, 500)
+                }),
+                Some(TouchEvent::Down {
+                    id: 2,
+                    point: p(900, 0)
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn jump_beyond_max_jump_is_a_new_finger() {
+        let mut tracker = Tracker::new().with_max_jump(100);
+        tracker.update(&report(&[Some(p(100, 100))]));
+
+        // within max_jump is a move
+        let e = events::<1>(tracker.update(&report(&[Some(p(160, 180))])));
+        assert_eq!(
+            e,
+            [Some(TouchEvent::Move {
+                id: 0,
+                point: p(160, 180)
+            })]
+        );
+
+        let e = events::<2>(tracker.update(&report(&[Some(p(600, 600))])));
+        assert_eq!(
+            e,
+            [
+                Some(TouchEvent::Up {
+                    id: 0,
+                    point: p(160, 180)
+                }),
+                Some(TouchEvent::Down {
+                    id: 1,
+                    point: p(600, 600)
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn full_range_jump_does_not_overflow() {
+        let mut tracker = Tracker::new();
+        tracker.update(&report(&[Some(p(0, 0))]));
+        let e = events::<2>(tracker.update(&report(&[Some(p(65534, 65534))])));
+        assert_eq!(
+            e[1],
+            Some(TouchEvent::Down {
+                id: 1,
+                point: p(65534, 65534)
+            })
+        );
+    }
+
+    #[test]
+    fn stale_reports_are_ignored() {
+        let mut tracker = Tracker::new();
+        tracker.update(&report(&[Some(p(10, 10))]));
+
+        let stale = TouchReport::from_points(&[], false);
+        assert_eq!(tracker.update(&stale).next(), None);
+        assert_eq!(tracker.fingers().count(), 1);
+    }
+
+    #[test]
+    fn alloc_id_skips_ids_still_down() {
+        let mut tracker = Tracker::new();
+        tracker.update(&report(&[Some(p(10, 10))]));
+        assert_eq!(tracker.fingers().next().map(|f| f.id), Some(0));
+
+        // wrap the counter around so the next candidate is the held id 0
+        tracker.next_id = 0;
+        let e = events::<1>(tracker.update(&report(&[Some(p(10, 10)), Some(p(300, 300))])));
+        assert_eq!(
+            e,
+            [Some(TouchEvent::Down {
+                id: 1,
+                point: p(300, 300)
+            })]
+        );
+    }
+
+    #[test]
+    fn reset_lifts_all_fingers() {
+        let mut tracker = Tracker::new();
+        tracker.update(&report(&[Some(p(10, 10)), None, Some(p(20, 20))]));
+        let e = events::<2>(tracker.reset());
+        assert_eq!(
+            e,
+            [
+                Some(TouchEvent::Up {
+                    id: 0,
+                    point: p(10, 10)
+                }),
+                Some(TouchEvent::Up {
+                    id: 1,
+                    point: p(20, 20)
+                }),
+            ]
+        );
+        assert_eq!(tracker.fingers().count(), 0);
+    }
+}
@@ -0,0 +1,226 @@
+use crate::Point;
+
+/// Clockwise rotation of the display relative to the sensor.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Rotation {
+    #[default]
+    Deg0,
+    Deg90,
+    Deg180,
+    Deg270,
+}
+
+/// Mapping from sensor coordinates to display coordinates.
+///
+/// Axis swap and inversion are applied first, in sensor space, then the rotation.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Transform {
+    pub rotation: Rotation,
+    pub swap_xy: bool,
+    pub invert_x: bool,
+    pub invert_y: bool,
+}
+
+impl Transform {
+    pub const IDENTITY: Self = Self {
+        rotation: Rotation::Deg0,
+        swap_xy: false,
+        invert_x: false,
+        invert_y: false,
+    };
+
+    pub const fn new(rotation: Rotation) -> Self {
+        Self {
+            rotation,
+            ..Self::IDENTITY
+        }
+    }
+
+    pub const fn swap_xy(mut self, swap: bool) -> Self {
+        self.swap_xy = swap;
+        self
+    }
+
+    pub const fn invert_x(mut self, invert: bool) -> Self {
+        self.invert_x = invert;
+        self
+    }
+
+    pub const fn invert_y(mut self, invert: bool) -> Self {
+        self.invert_y = invert;
+        self
+    }
+
+    pub fn is_identity(&self) -> bool {
+        *self == Self::IDENTITY
+    }
+
+    /// Size of the output coordinate space for a `width` x `height` sensor.
+    pub fn size(&self, width: u16, height: u16) -> (u16, u16) {
+        let transposed = self.swap_xy ^ matches!(self.rotation, Rotation::Deg90 | Rotation::Deg270);
+        if transposed {
+            (height, width)
+        } else {
+            (width, height)
+        }
+    }
+
+    /// Map a point of a `width` x `height` sensor, with coordinates in `0..width` and `0..height`.
+    pub fn apply(&self, point: Point, width: u16, height: u16) -> Point {
+        let (mut x, mut y) = (point.x, point.y);
+        let (mut w, mut h) = (width, height);
+
+        if self.swap_xy {
+            (x, y) = (y, x);
+            (w, h) = (h, w);
+        }
+        if self.invert_x {
+            x = flip(x, w);
+        }
+        if self.invert_y {
+            y = flip(y, h);
+        }
+
+        let (x, y) = match self.rotation {
+            Rotation::Deg0 => (x, y),
+            Rotation::Deg90 => (flip(y, h), x),
+            Rotation::Deg180 => (flip(x, w), flip(y, h)),
+            Rotation::Deg270 => (y, flip(x, w)),
+        };
+        Point { x, y }
+    }
+}
+
+fn flip(v: u16, len: u16) -> u16 {
+    len.saturating_sub(1).saturating_sub(v)
+}
+
+/// Rescale a coordinate from `0..from` to `0..to`, rounding to nearest and clamping.
+pub(crate) fn scale(v: u16, from: u16, to: u16) -> u16 {
+    if from <= 1 || to == 0 {
+        return 0;
+    }
+    let (from, to) = (u32::from(from - 1), u32::from(to - 1));
+    let v = u32::from(v).min(from);
+    ((v * to + from / 2) / from) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROTATIONS: [Rotation; 4] = [
+        Rotation::Deg0,
+        Rotation::Deg90,
+        Rotation::Deg180,
+        Rotation::Deg270,
+    ];
+
+    fn p(x: u16, y: u16) -> Point {
+        Point { x, y }
+    }
+
+    #[test]
+    fn rotations() {
+        // 100 x 50 sensor
+        let point = p(10, 20);
+        for (rotation, expected) in [
+            (Rotation::Deg0, p(10, 20)),
+            (Rotation::Deg90, p(29, 10)),
+            (Rotation::Deg180, p(89, 29)),
+            (Rotation::Deg270, p(20, 89)),
+        ] {
+            assert_eq!(
+                Transform::new(rotation).apply(point, 100, 50),
+                expected,
+                "{rotation:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn swap_and_invert() {
+        let point = p(10, 20);
+        for (transform, expected) in [
+            (Transform::IDENTITY.swap_xy(true), p(20, 10)),
+            (Transform::IDENTITY.invert_x(true), p(89, 20)),
+            (Transform::IDENTITY.invert_y(true), p(10, 29)),
+            (Transform::IDENTITY.invert_x(true).invert_y(true), p(89, 29)),
+            // invert applies to the swapped axes
+            (Transform::IDENTITY.swap_xy(true).invert_x(true), p(29, 10)),
+            // swap first, then rotate in the swapped 50 x 100 space
+            (Transform::new(Rotation::Deg90).swap_xy(true), p(89, 20)),
+            (Transform::new(Rotation::Deg270).invert_y(true), p(29, 89)),
+        ] {
+            assert_eq!(transform.apply(point, 100, 50), expected, "{transform:?}");
+        }
+    }
+
+    #[test]
+    fn output_size_is_transposed() {
+        for rotation in ROTATIONS {
+            for swap in [false, true] {
+                let transform = Transform::new(rotation).swap_xy(swap);
+                let quarter = matches!(rotation, Rotation::Deg90 | Rotation::Deg270);
+                let expected = if quarter ^ swap { (50, 100) } else { (100, 50) };
+                assert_eq!(transform.size(100, 50), expected, "{transform:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn every_combination_maps_sensor_onto_output() {
+        let (width, height) = (5u16, 3u16);
+        for rotation in ROTATIONS {
+            for bits in 0..8 {
+                let transform = Transform::new(rotation)
+                    .swap_xy(bits & 1 != 0)
+                    .invert_x(bits & 2 != 0)
+                    .invert_y(bits & 4 != 0);
+                let (out_width, out_height) = transform.size(width, height);
+
+                // within the output size, and no two sensor points map to the same output
+                let mut seen = [[false; 5]; 5];
+                for x in 0..width {
+                    for y in 0..height {
+                        let q = transform.apply(p(x, y), width, height);
+                        assert!(q.x < out_width && q.y < out_height, "{transform:?} {q:?}");
+                        assert!(!seen[q.x as usize][q.y as usize], "{transform:?} {q:?}");
+                        seen[q.x as usize][q.y as usize] = true;
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn scale_edges() {
+        // degenerate ranges
+        assert_eq!(scale(0, 0, 100), 0);
+        assert_eq!(scale(5, 1, 100), 0);
+        assert_eq!(scale(5, 100, 0), 0);
+        // ends map to ends
+        assert_eq!(scale(0, 100, 800), 0);
+        assert_eq!(scale(99, 100, 800), 799);
+        assert_eq!(scale(4095, 4096, 480), 479);
+        // values above the input range clamp to the last output coordinate
+        assert_eq!(scale(100, 100, 800), 799);
+        assert_eq!(scale(u16::MAX, 100, 800), 799);
+        // round to nearest, over `0..=from-1` to `0..=to-1`: 4/2 = 2, 1/2 rounds up, 2/3 up, 1/3 down
+        assert_eq!(scale(1, 3, 5), 2);
+        assert_eq!(scale(1, 3, 2), 1);
+        assert_eq!(scale(1, 4, 3), 1);
+        assert_eq!(scale(1, 4, 2), 0);
+        // full u16 range does not overflow
+        assert_eq!(scale(u16::MAX - 1, u16::MAX, u16::MAX), u16::MAX - 1);
+    }
+
+    #[test]
+    fn identity() {
+        assert!(Transform::default().is_identity());
+        assert!(!Transform::new(Rotation::Deg180).is_identity());
+        assert_eq!(Transform::IDENTITY.apply(p(7, 9), 10, 10), p(7, 9));
+    }
+}